@@ -0,0 +1,219 @@
+use crate::items::{Item, Loc, PackedItem};
+use crate::packs::Pack;
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+/// A change of position and/or orientation about to be applied to a
+/// packed item. Moves and transpositions both arrive as a `Motion`, so a
+/// behavior can redirect or cancel either.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Motion {
+    pub dst: Loc,
+    pub transpose: bool,
+}
+
+/// What happens to an item after it has been used.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum UseOutcome {
+    #[default]
+    Keep,
+    /// The item is used up and leaves the pack.
+    Consume,
+}
+
+/// Game logic attached to an item. Packs call these hooks before committing
+/// an operation on the item; returning an error vetoes the operation and
+/// leaves the pack untouched. Every hook gets read access to the pack so it
+/// can look at the item's surroundings.
+pub trait ItemBehavior {
+    /// The item is about to be added at `loc`. Both may be adjusted.
+    fn on_add(&self, _item: &mut Item, _loc: &mut Loc, _pack: &dyn Pack) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// The item is about to be taken out of the pack.
+    fn on_remove(&self, _item: &PackedItem, _pack: &dyn Pack) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// The item is about to be moved or transposed. The motion may be adjusted.
+    fn on_move(
+        &self,
+        _item: &PackedItem,
+        _motion: &mut Motion,
+        _pack: &dyn Pack,
+    ) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// The item is being used where it lies.
+    fn on_use(&self, _item: &PackedItem, _pack: &dyn Pack) -> Result<UseOutcome, String> {
+        Ok(UseOutcome::Keep)
+    }
+}
+
+/// A shared handle to an `ItemBehavior`. Items of one kind usually share a
+/// single behavior; two handles are equal when they point at the same one.
+#[derive(Clone)]
+pub struct Behavior(Rc<dyn ItemBehavior>);
+
+impl Behavior {
+    pub fn new<B: ItemBehavior + 'static>(behavior: B) -> Self {
+        Behavior(Rc::new(behavior))
+    }
+}
+
+impl std::ops::Deref for Behavior {
+    type Target = dyn ItemBehavior;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl PartialEq for Behavior {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::as_ptr(&self.0) as *const () == Rc::as_ptr(&other.0) as *const ()
+    }
+}
+
+impl Eq for Behavior {}
+
+impl Hash for Behavior {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (Rc::as_ptr(&self.0) as *const ()).hash(state);
+    }
+}
+
+impl fmt::Debug for Behavior {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Behavior({:p})", Rc::as_ptr(&self.0) as *const ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::Kind;
+    use crate::packs::{map_pack::MapPack, DensePack};
+
+    struct Cursed;
+
+    impl ItemBehavior for Cursed {
+        fn on_remove(&self, item: &PackedItem, _pack: &dyn Pack) -> Result<(), String> {
+            Err(format!("The {} refuses to leave.", item.name()))
+        }
+
+        fn on_move(
+            &self,
+            _item: &PackedItem,
+            motion: &mut Motion,
+            _pack: &dyn Pack,
+        ) -> Result<(), String> {
+            if motion.transpose {
+                return Err("The cursed item will not turn.".to_string());
+            }
+            Ok(())
+        }
+    }
+
+    /// Always lands in the top-left corner, whatever spot it was dropped on.
+    struct Homing;
+
+    impl ItemBehavior for Homing {
+        fn on_add(&self, _item: &mut Item, loc: &mut Loc, _pack: &dyn Pack) -> Result<(), String> {
+            *loc = Loc::new(0, 0);
+            Ok(())
+        }
+    }
+
+    struct Edible;
+
+    impl ItemBehavior for Edible {
+        fn on_use(&self, _item: &PackedItem, _pack: &dyn Pack) -> Result<UseOutcome, String> {
+            Ok(UseOutcome::Consume)
+        }
+    }
+
+    #[test]
+    fn vetoed_removal_leaves_item_in_place() {
+        let mut pack = DensePack::new(2, 2);
+        let idol = Item::new("idol", 1, 1, 'I')
+            .with_kind(Kind::new("relic").with_behavior(Behavior::new(Cursed)));
+
+        let result = pack.add_item(idol, Loc::new(0, 0));
+        assert!(result.is_ok());
+
+        assert!(pack.take_item(&Loc::new(0, 0)).is_err());
+        assert_eq!(
+            pack.remove_item("idol").err(),
+            Some("The idol refuses to leave.".to_string())
+        );
+        assert!(pack.grab_item(&Loc::new(0, 0)).is_some());
+    }
+
+    #[test]
+    fn vetoed_transposition_is_an_error_but_moves_still_work() {
+        let mut pack = MapPack::new(3, 3);
+        let idol = Item::new("idol", 1, 2, 'I')
+            .with_kind(Kind::new("relic").with_behavior(Behavior::new(Cursed)));
+
+        let result = pack.add_item(idol, Loc::new(0, 0));
+        assert!(result.is_ok());
+
+        assert!(pack.transpose_item(Loc::new(0, 0)).is_err());
+        assert_eq!(
+            pack.move_item(Loc::new(0, 0), Loc::new(2, 1)),
+            Ok(Loc::new(2, 1))
+        );
+        assert_eq!(
+            pack.remove_item(Loc::new(2, 1)).err(),
+            Some("The idol refuses to leave.".to_string())
+        );
+    }
+
+    #[test]
+    fn add_hook_can_redirect_placement() {
+        let mut pack = DensePack::new(2, 2);
+        let magnet = Item::new("magnet", 1, 1, 'M')
+            .with_kind(Kind::new("magnet").with_behavior(Behavior::new(Homing)));
+
+        assert_eq!(pack.add_item(magnet, Loc::new(1, 1)), Ok(Loc::new(0, 0)));
+        assert!(pack.grab_item(&Loc::new(1, 1)).is_none());
+    }
+
+    #[test]
+    fn consumed_items_leave_the_pack() {
+        let mut pack = MapPack::new(1, 3);
+        let apple = Item::new("apple", 1, 2, 'a')
+            .with_kind(Kind::new("food").with_behavior(Behavior::new(Edible)));
+        let stone = Item::new("stone", 1, 1, '*');
+
+        assert!(pack.add_item(apple, Loc::new(0, 0)).is_ok());
+        assert!(pack.add_item(stone, Loc::new(0, 2)).is_ok());
+
+        assert_eq!(pack.use_item_at(Loc::new(0, 1)), Ok(UseOutcome::Consume));
+        assert_eq!(pack.use_item_at(Loc::new(0, 2)), Ok(UseOutcome::Keep));
+        assert!(pack.item_at(&Loc::new(0, 0)).is_none());
+        assert!(pack.item_at(&Loc::new(0, 2)).is_some());
+    }
+
+    #[test]
+    fn items_of_a_kind_share_its_behavior() {
+        let mut pack = DensePack::new(1, 3);
+        let relic = Kind::new("relic").with_behavior(Behavior::new(Cursed));
+        for (name, col) in [("idol", 0), ("mask", 1)] {
+            let item = Item::new(name, 1, 1, 'I').with_kind(relic.clone());
+            assert!(pack.add_item(item, Loc::new(0, col)).is_ok());
+        }
+        // A kind named alike but without behavior is just a label.
+        let vase = Item::new("vase", 1, 1, 'v').with_kind("relic");
+        assert!(pack.add_item(vase, Loc::new(0, 2)).is_ok());
+
+        assert!(pack.remove_item_at(Loc::new(0, 0)).is_err());
+        assert!(pack.remove_item_at(Loc::new(0, 1)).is_err());
+        assert!(pack.remove_item_at(Loc::new(0, 2)).is_ok());
+    }
+}
@@ -1,9 +1,182 @@
-#[derive(Debug, Clone, Eq, PartialEq)]
+use crate::behavior::Behavior;
+
+use std::fmt;
+
+/// A terminal color for drawing an item's symbol.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    /// An entry of the 256-color palette.
+    Ansi(u8),
+    /// A 24-bit color.
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// The colors with names, as the journal and the text format spell them.
+    pub(crate) const NAMED: [(&'static str, Color); 8] = [
+        ("black", Color::Black),
+        ("red", Color::Red),
+        ("green", Color::Green),
+        ("yellow", Color::Yellow),
+        ("blue", Color::Blue),
+        ("magenta", Color::Magenta),
+        ("cyan", Color::Cyan),
+        ("white", Color::White),
+    ];
+
+    /// The color's name, unless it is a palette entry or a 24-bit color.
+    pub(crate) fn name(self) -> Option<&'static str> {
+        Color::NAMED
+            .iter()
+            .find(|(_, color)| *color == self)
+            .map(|(name, _)| *name)
+    }
+
+    pub(crate) fn named(name: &str) -> Option<Color> {
+        Color::NAMED
+            .iter()
+            .find(|(other, _)| *other == name)
+            .map(|(_, color)| *color)
+    }
+}
+
+/// Whether an item lies as it was created or has been transposed.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, PartialOrd, Ord, Hash)]
+pub enum Orientation {
+    #[default]
+    Upright,
+    Transposed,
+}
+
+impl Orientation {
+    pub fn flipped(self) -> Self {
+        match self {
+            Orientation::Upright => Orientation::Transposed,
+            Orientation::Transposed => Orientation::Upright,
+        }
+    }
+}
+
+impl fmt::Display for Orientation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Orientation::Upright => write!(f, "upright"),
+            Orientation::Transposed => write!(f, "transposed"),
+        }
+    }
+}
+
+/// What sort of thing an item is, e.g. "tool" or "gem". Game logic is
+/// attached to a kind rather than to single items, so every item of the
+/// kind behaves alike.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Kind {
+    name: String,
+    behavior: Option<Behavior>,
+}
+
+impl Kind {
+    pub fn new(name: &str) -> Self {
+        Kind {
+            name: String::from(name),
+            behavior: None,
+        }
+    }
+
+    /// Attach game logic that runs when an item of this kind is added,
+    /// removed, moved or used.
+    pub fn with_behavior(mut self, behavior: Behavior) -> Self {
+        self.behavior = Some(behavior);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn behavior(&self) -> Option<&Behavior> {
+        self.behavior.as_ref()
+    }
+}
+
+impl From<&str> for Kind {
+    fn from(name: &str) -> Self {
+        Kind::new(name)
+    }
+}
+
+/// An item as the journal and the text format save it, with its size as it
+/// lies in the pack. Kinds are saved by name only, so no behavior comes back.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SavedItem {
+    pub name: String,
+    pub rows: u32,
+    pub cols: u32,
+    pub orientation: Orientation,
+    pub symbol: String,
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub kind: Option<String>,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub value: u32,
+    pub weight: u32,
+}
+
+impl SavedItem {
+    pub(crate) fn build(self) -> Item {
+        // Build the item as it was created, then turn it if it was turned.
+        let mut item = match self.orientation {
+            Orientation::Upright => Item::new(&self.name, self.rows, self.cols, ' '),
+            Orientation::Transposed => Item::new(&self.name, self.cols, self.rows, ' '),
+        }
+        .with_symbol(&self.symbol)
+        .with_value(self.value)
+        .with_weight(self.weight);
+        if let Some(fg) = self.fg {
+            item = item.with_fg(fg);
+        }
+        if let Some(bg) = self.bg {
+            item = item.with_bg(bg);
+        }
+        if let Some(kind) = &self.kind {
+            item = item.with_kind(kind.as_str());
+        }
+        if let Some(description) = &self.description {
+            item = item.with_description(description);
+        }
+        for tag in &self.tags {
+            item = item.with_tag(tag);
+        }
+        if self.orientation == Orientation::Transposed {
+            item.transpose();
+        }
+        item
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct Item {
     name: String,
     rows: u32,
     cols: u32,
-    symbol: char,
+    symbol: String,
+    fg: Option<Color>,
+    bg: Option<Color>,
+    kind: Option<Kind>,
+    description: Option<String>,
+    tags: Vec<String>,
+    value: u32,
+    weight: u32,
+    orientation: Orientation,
 }
 
 impl Item {
@@ -20,14 +193,75 @@ impl Item {
             name: String::from(name),
             rows,
             cols,
-            symbol,
+            symbol: symbol.to_string(),
+            fg: None,
+            bg: None,
+            kind: None,
+            description: None,
+            tags: Vec::new(),
+            value: 0,
+            weight: 0,
+            orientation: Orientation::default(),
+        }
+    }
+
+    /// Replace the symbol with a glyph that may span several code points,
+    /// such as an emoji with a variation selector.
+    pub fn with_symbol(mut self, symbol: &str) -> Self {
+        self.symbol = String::from(symbol);
+        self
+    }
+
+    pub fn with_fg(mut self, fg: Color) -> Self {
+        self.fg = Some(fg);
+        self
+    }
+
+    pub fn with_bg(mut self, bg: Color) -> Self {
+        self.bg = Some(bg);
+        self
+    }
+
+    /// Group the item with others of the same kind, e.g. "tool" or "gem".
+    /// The item takes on the kind's behavior, if it has one.
+    pub fn with_kind(mut self, kind: impl Into<Kind>) -> Self {
+        self.kind = Some(kind.into());
+        self
+    }
+
+    /// Attach free-form text describing the item.
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = Some(String::from(description));
+        self
+    }
+
+    /// Label the item, e.g. "flammable". An item may carry any number of tags.
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        if !self.tags.iter().any(|existing| existing == tag) {
+            self.tags.push(String::from(tag));
         }
+        self
+    }
+
+    /// What the item is worth, in whatever unit the game trades in.
+    pub fn with_value(mut self, value: u32) -> Self {
+        self.value = value;
+        self
+    }
+
+    pub fn with_weight(mut self, weight: u32) -> Self {
+        self.weight = weight;
+        self
     }
 
     pub fn name(&self) -> &str {
         &self.name
     }
 
+    pub fn rename(&mut self, name: &str) {
+        self.name = String::from(name);
+    }
+
     pub fn rows(&self) -> u32 {
         self.rows
     }
@@ -36,12 +270,55 @@ impl Item {
         self.cols
     }
 
-    pub fn symbol(&self) -> char {
-        self.symbol
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    pub fn fg(&self) -> Option<Color> {
+        self.fg
+    }
+
+    pub fn bg(&self) -> Option<Color> {
+        self.bg
+    }
+
+    pub fn kind(&self) -> Option<&str> {
+        self.kind.as_ref().map(Kind::name)
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    pub fn value(&self) -> u32 {
+        self.value
+    }
+
+    pub fn weight(&self) -> u32 {
+        self.weight
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    /// The behavior of the item's kind.
+    pub fn behavior(&self) -> Option<&Behavior> {
+        self.kind.as_ref().and_then(Kind::behavior)
+    }
+
+    /// Swap rows and cols, flipping the orientation.
+    pub fn transpose(&mut self) {
+        std::mem::swap(&mut self.rows, &mut self.cols);
+        self.orientation = self.orientation.flipped();
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Ord, Copy)]
+#[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Ord, Copy, Hash)]
 pub struct Loc {
     row: u32,
     col: u32,
@@ -61,7 +338,102 @@ impl Loc {
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+/// One of the four orthogonal directions on the grid.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ];
+}
+
+impl Loc {
+    /// The neighbouring location one cell away, or None past row or
+    /// column zero.
+    pub fn step(&self, direction: Direction) -> Option<Loc> {
+        match direction {
+            Direction::Up => Some(Loc::new(self.row.checked_sub(1)?, self.col)),
+            Direction::Down => Some(Loc::new(self.row + 1, self.col)),
+            Direction::Left => Some(Loc::new(self.row, self.col.checked_sub(1)?)),
+            Direction::Right => Some(Loc::new(self.row, self.col + 1)),
+        }
+    }
+}
+
+impl fmt::Display for Loc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}, {})", self.row, self.col)
+    }
+}
+
+/// An axis-aligned block of cells anchored at its top-left `Loc`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, PartialOrd, Ord, Hash)]
+pub struct Rect {
+    loc: Loc,
+    rows: u32,
+    cols: u32,
+}
+
+impl Rect {
+    pub fn new(loc: Loc, rows: u32, cols: u32) -> Self {
+        Rect { loc, rows, cols }
+    }
+
+    pub fn loc(&self) -> Loc {
+        self.loc
+    }
+
+    pub fn row(&self) -> u32 {
+        self.loc.row
+    }
+
+    pub fn col(&self) -> u32 {
+        self.loc.col
+    }
+
+    pub fn rows(&self) -> u32 {
+        self.rows
+    }
+
+    pub fn cols(&self) -> u32 {
+        self.cols
+    }
+
+    pub fn area(&self) -> u32 {
+        self.rows * self.cols
+    }
+
+    pub fn contains(&self, loc: &Loc) -> bool {
+        self.row() <= loc.row
+            && loc.row < self.row() + self.rows
+            && self.col() <= loc.col
+            && loc.col < self.col() + self.cols
+    }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        !(self.col() + self.cols <= other.col()
+            || self.col() >= other.col() + other.cols
+            || self.row() + self.rows <= other.row()
+            || self.row() >= other.row() + other.rows)
+    }
+}
+
+impl fmt::Display for Rect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} x {} at {}", self.rows, self.cols, self.loc)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct PackedItem {
     item: Item,
     loc: Loc,
@@ -76,8 +448,12 @@ impl PackedItem {
         self.item
     }
 
+    pub fn item(&self) -> &Item {
+        &self.item
+    }
+
     pub fn loc(&self) -> Loc {
-        self.loc.clone()
+        self.loc
     }
 
     pub fn row(&self) -> u32 {
@@ -89,7 +465,7 @@ impl PackedItem {
     }
 
     pub fn name(&self) -> &str {
-        &self.item.name()
+        self.item.name()
     }
 
     pub fn rows(&self) -> u32 {
@@ -100,10 +476,51 @@ impl PackedItem {
         self.item.cols()
     }
 
-    pub fn symbol(&self) -> char {
+    pub fn symbol(&self) -> &str {
         self.item.symbol()
     }
 
+    pub fn fg(&self) -> Option<Color> {
+        self.item.fg()
+    }
+
+    pub fn bg(&self) -> Option<Color> {
+        self.item.bg()
+    }
+
+    pub fn kind(&self) -> Option<&str> {
+        self.item.kind()
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.item.description()
+    }
+
+    pub fn tags(&self) -> &[String] {
+        self.item.tags()
+    }
+
+    pub fn value(&self) -> u32 {
+        self.item.value()
+    }
+
+    pub fn weight(&self) -> u32 {
+        self.item.weight()
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        self.item.orientation()
+    }
+
+    pub fn behavior(&self) -> Option<&Behavior> {
+        self.item.behavior()
+    }
+
+    /// The cells the item covers.
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.loc, self.rows(), self.cols())
+    }
+
     pub fn intersects(&self, other: &Self) -> bool {
         if
         // self is entirely to the left of other.
@@ -132,10 +549,88 @@ impl PackedItem {
     }
 
     pub fn transpose(&mut self) {
-        std::mem::swap(&mut self.item.rows, &mut self.item.cols);
+        self.item.transpose();
     }
 
     pub fn move_to(&mut self, dst: Loc) {
         self.loc = dst;
     }
+
+    /// A multi-line, human-readable account of the item, one `field: value`
+    /// per line. Fields the item does not set are left out.
+    pub fn describe(&self) -> String {
+        let mut lines = vec![format!("name: {}", self.name())];
+        if let Some(kind) = self.kind() {
+            lines.push(format!("kind: {}", kind));
+        }
+        if let Some(description) = self.description() {
+            lines.push(format!("description: {}", description));
+        }
+        lines.push(format!("size: {} x {}", self.rows(), self.cols()));
+        lines.push(format!("anchor: {}", self.loc()));
+        lines.push(format!("orientation: {}", self.orientation()));
+        lines.push(format!("symbol: {}", self.symbol()));
+        if let Some(fg) = self.fg() {
+            lines.push(format!("foreground: {:?}", fg));
+        }
+        if let Some(bg) = self.bg() {
+            lines.push(format!("background: {:?}", bg));
+        }
+        lines.push(format!("value: {}", self.value()));
+        lines.push(format!("weight: {}", self.weight()));
+        if !self.tags().is_empty() {
+            lines.push(format!("tags: {}", self.tags().join(", ")));
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describe_lists_every_field_that_is_set() {
+        let item = Item::new("lamp", 1, 2, 'l')
+            .with_kind("tool")
+            .with_description("Lights the way.")
+            .with_fg(Color::Yellow)
+            .with_value(12)
+            .with_weight(3)
+            .with_tag("fragile")
+            .with_tag("bright");
+        let mut packed_item = PackedItem::new(Loc::new(2, 1), item);
+        packed_item.transpose();
+
+        assert_eq!(
+            packed_item.describe(),
+            "name: lamp\n\
+             kind: tool\n\
+             description: Lights the way.\n\
+             size: 2 x 1\n\
+             anchor: (2, 1)\n\
+             orientation: transposed\n\
+             symbol: l\n\
+             foreground: Yellow\n\
+             value: 12\n\
+             weight: 3\n\
+             tags: fragile, bright"
+        );
+    }
+
+    #[test]
+    fn describe_leaves_out_missing_kind_and_description() {
+        let packed_item = PackedItem::new(Loc::new(0, 0), Item::new("pebble", 1, 1, '.'));
+
+        assert_eq!(
+            packed_item.describe(),
+            "name: pebble\n\
+             size: 1 x 1\n\
+             anchor: (0, 0)\n\
+             orientation: upright\n\
+             symbol: .\n\
+             value: 0\n\
+             weight: 0"
+        );
+    }
 }
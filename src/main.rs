@@ -1,69 +1,280 @@
 #![allow(dead_code)]
 
+mod behavior;
 mod items;
 mod packs;
 
-use std::io::{stdin, stdout, Write};
+use std::io::{stdin, stdout, IsTerminal, Write};
 use std::str::SplitWhitespace;
 
-use crate::items::{Item, Loc};
+use crate::items::{Direction, Item, Loc};
 use crate::packs::DensePack as Pack;
+use crate::packs::{load_best, transfer, LoadOptions, Placement};
+use crate::packs::{Grouping, NamePolicy, RenderMode, SortKey, SortOrder, SortStrategy};
+use crate::packs::{Pack as _, PackMut as _};
 
 fn help_message() -> &'static str {
     let help: &'static str = "
 Commands:\n\
 exit\n\
 help\n\
-show store\n\
-show pack\n\
+showstore [bordered]\n\
+showpack [bordered]\n\
 pack <src row> <src col> <target row> <target col>\n\
 store <src row> <src col> <target row> <target col>\n\
 packtranspose  <src row> <src col>\n\
+packpush <src row> <src col> <target row> <target col>\n\
+packslide <src row> <src col> <up|down|left|right> [steps]\n\
+inspect <row> <col>\n\
+inspectstore <row> <col>\n\
+stats\n\
+sort [kind|tag] [size|name|value] [rows|cols]\n\
+sortstore [kind|tag] [size|name|value] [rows|cols]\n\
+bestload [weight limit]\n\
+undo\n\
+redo\n\
 ";
     help
 }
 
 fn move_between(mut words: SplitWhitespace, src: &mut Pack, dst: &mut Pack) -> Result<(), String> {
-    let src_row = match words.next().and_then(|s| s.parse::<u32>().ok()) {
+    let src_loc = read_loc(&mut words)?;
+    let dst_loc = read_loc(&mut words)?;
+    let placement = Placement {
+        src: src_loc,
+        dst: dst_loc,
+        transpose: false,
+    };
+    transfer(src, dst, &[placement])
+}
+
+fn read_loc(words: &mut SplitWhitespace) -> Result<Loc, String> {
+    let row = match words.next().and_then(|s| s.parse::<u32>().ok()) {
         Some(row) => row,
         None => {
             return Err("Expected a non-negative integer row coordinate value.".to_string());
         }
     };
 
-    let src_col = match words.next().and_then(|s| s.parse::<u32>().ok()) {
+    let col = match words.next().and_then(|s| s.parse::<u32>().ok()) {
         Some(col) => col,
         None => {
             return Err("Expected a non-negative integer column coordinate value.".to_string());
         }
     };
-    let src_loc = Loc::new(src_row, src_col);
-    let src_item = src.take_item(&src_loc);
-    if src_item.is_none() {
-        return Err("No item there.".to_string());
+    Ok(Loc::new(row, col))
+}
+
+fn inspect(mut words: SplitWhitespace, target: &Pack) -> Result<String, String> {
+    let loc = read_loc(&mut words)?;
+    match target.grab_item(&loc) {
+        Some(packed_item) => Ok(packed_item.describe()),
+        None => Err("No item there.".to_string()),
     }
-    let src_item = src_item.unwrap();
+}
 
-    let dst_row = match words.next().and_then(|s| s.parse::<u32>().ok()) {
-        Some(row) => row,
-        None => {
-            return Err("Expected a non-negative integer row coordinate value.".to_string());
+fn sort(words: SplitWhitespace, target: &mut Pack) -> Result<(), String> {
+    let mut key = SortKey::default();
+    let mut strategy = SortStrategy::default();
+    for word in words {
+        match word {
+            "kind" => key.group = Grouping::Kind,
+            "tag" => key.group = Grouping::Tag,
+            "size" => key.order = SortOrder::Size,
+            "name" => key.order = SortOrder::Name,
+            "value" => key.order = SortOrder::Value,
+            "rows" => strategy = SortStrategy::RowMajor,
+            "cols" => strategy = SortStrategy::ColumnMajor,
+            _ => return Err(format!("Unknown sort option {}", word)),
         }
-    };
+    }
+    target.sort_by(key, strategy)
+}
 
-    let dst_col = match words.next().and_then(|s| s.parse::<u32>().ok()) {
-        Some(col) => col,
-        None => {
-            return Err("Expected a non-negative integer column coordinate value.".to_string());
+fn best_load(
+    mut words: SplitWhitespace,
+    store: &mut Pack,
+    pack: &mut Pack,
+) -> Result<String, String> {
+    let mut options = LoadOptions::default();
+    if let Some(word) = words.next() {
+        match word.parse::<u32>() {
+            Ok(limit) => options.weight_limit = Some(limit),
+            Err(_) => {
+                return Err("Expected a non-negative integer weight limit.".to_string());
+            }
         }
+    }
+    let load = load_best(store, pack, options)?;
+    let quality = if load.exact { "best" } else { "greedy" };
+    Ok(format!(
+        "Packed {} items worth {} weighing {} ({} load).",
+        load.placements.len(),
+        load.value,
+        load.weight,
+        quality
+    ))
+}
+
+fn push(mut words: SplitWhitespace, target: &mut Pack) -> Result<String, String> {
+    let src = read_loc(&mut words)?;
+    let dst = read_loc(&mut words)?;
+    let pushed = target.move_with_push(src, dst)?;
+    Ok(pushed
+        .iter()
+        .map(|shift| {
+            format!(
+                "Pushed the {} from {} to {}.",
+                shift.name, shift.from, shift.to
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n"))
+}
+
+fn slide(mut words: SplitWhitespace, target: &mut Pack) -> Result<Loc, String> {
+    let src = read_loc(&mut words)?;
+    let direction = match words.next() {
+        Some("up") => Direction::Up,
+        Some("down") => Direction::Down,
+        Some("left") => Direction::Left,
+        Some("right") => Direction::Right,
+        _ => return Err("Expected a direction: up, down, left or right.".to_string()),
+    };
+    let steps = match words.next() {
+        Some(word) => match word.parse::<u32>() {
+            Ok(steps) => steps,
+            Err(_) => return Err("Expected a non-negative number of steps.".to_string()),
+        },
+        None => 1,
     };
-    let dst_loc = Loc::new(dst_row, dst_col);
-    dst.add_item(src_item, dst_loc)?;
+    target.slide(src, direction, steps)
+}
+
+fn render_mode(mut words: SplitWhitespace) -> RenderMode {
+    match words.next() {
+        Some("bordered") => RenderMode::Bordered,
+        _ => RenderMode::Plain,
+    }
+}
+
+/// How many changes one command made to each pack.
+#[derive(Debug, Clone, Copy, Default)]
+struct Step {
+    store: u64,
+    pack: u64,
+}
+
+/// Commands that can be undone and redone. A command that changes both
+/// packs, like moving an item between them, is one step.
+#[derive(Debug, Default)]
+struct Timeline {
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+}
+
+impl Timeline {
+    /// Where both histories stand now.
+    fn mark(store: &Pack, pack: &Pack) -> Step {
+        Step {
+            store: store.history().recorded(),
+            pack: pack.history().recorded(),
+        }
+    }
 
+    /// Turn whatever was recorded since `before` into one step.
+    fn record(&mut self, before: Step, store: &Pack, pack: &Pack) {
+        let after = Timeline::mark(store, pack);
+        let step = Step {
+            store: after.store.saturating_sub(before.store),
+            pack: after.pack.saturating_sub(before.pack),
+        };
+        if step.store > 0 || step.pack > 0 {
+            self.undo.push(step);
+            self.redo.clear();
+        }
+    }
+
+    /// Undo the last step in both packs, or in neither if either cannot.
+    fn undo(&mut self, store: &mut Pack, pack: &mut Pack) -> Result<(), String> {
+        let step = match self.undo.pop() {
+            Some(step) => step,
+            None => return Err("Nothing to undo.".to_string()),
+        };
+        if (pack.history().undo_depth() as u64) < step.pack
+            || (store.history().undo_depth() as u64) < step.store
+        {
+            self.undo.push(step);
+            return Err("That step is too old to undo.".to_string());
+        }
+        if let Err(message) = undo_all(pack, step.pack) {
+            self.undo.push(step);
+            return Err(message);
+        }
+        if let Err(message) = undo_all(store, step.store) {
+            let _ = redo_all(pack, step.pack);
+            self.undo.push(step);
+            return Err(message);
+        }
+        self.redo.push(step);
+        Ok(())
+    }
+
+    /// Redo the last undone step in both packs, or in neither if either
+    /// cannot.
+    fn redo(&mut self, store: &mut Pack, pack: &mut Pack) -> Result<(), String> {
+        let step = match self.redo.pop() {
+            Some(step) => step,
+            None => return Err("Nothing to redo.".to_string()),
+        };
+        if (store.history().redo_depth() as u64) < step.store
+            || (pack.history().redo_depth() as u64) < step.pack
+        {
+            self.redo.push(step);
+            return Err("That step can no longer be redone.".to_string());
+        }
+        if let Err(message) = redo_all(store, step.store) {
+            self.redo.push(step);
+            return Err(message);
+        }
+        if let Err(message) = redo_all(pack, step.pack) {
+            let _ = undo_all(store, step.store);
+            self.redo.push(step);
+            return Err(message);
+        }
+        self.undo.push(step);
+        Ok(())
+    }
+}
+
+/// Undo the last `count` changes of `target`, or none of them if one fails.
+fn undo_all(target: &mut Pack, count: u64) -> Result<(), String> {
+    for done in 0..count {
+        if let Err(message) = target.undo() {
+            for _ in 0..done {
+                let _ = target.redo();
+            }
+            return Err(message);
+        }
+    }
+    Ok(())
+}
+
+/// Redo the last `count` undone changes of `target`, or none of them if one
+/// fails.
+fn redo_all(target: &mut Pack, count: u64) -> Result<(), String> {
+    for done in 0..count {
+        if let Err(message) = target.redo() {
+            for _ in 0..done {
+                let _ = target.undo();
+            }
+            return Err(message);
+        }
+    }
     Ok(())
 }
 
-fn interact(store: &mut Pack, pack: &mut Pack) -> bool {
+fn interact(store: &mut Pack, pack: &mut Pack, timeline: &mut Timeline) -> bool {
     // Read from stdin.
     let mut buffer = String::new();
     let io_res = stdin().read_line(&mut buffer);
@@ -72,7 +283,7 @@ fn interact(store: &mut Pack, pack: &mut Pack) -> bool {
     }
 
     // Some pre-processing.
-    let mut words = buffer.trim().split_whitespace();
+    let mut words = buffer.split_whitespace();
 
     // Consume the command token.
     let command = words.next();
@@ -89,11 +300,13 @@ fn interact(store: &mut Pack, pack: &mut Pack) -> bool {
             return true;
         }
         "showstore" => {
-            println!("{}", store);
+            let color = stdout().is_terminal();
+            println!("{}", store.render(render_mode(words)).with_color(color));
             return true;
         }
         "showpack" => {
-            println!("{}", pack);
+            let color = stdout().is_terminal();
+            println!("{}", pack.render(render_mode(words)).with_color(color));
             return true;
         }
         "pack" => {
@@ -133,26 +346,117 @@ fn interact(store: &mut Pack, pack: &mut Pack) -> bool {
             }
             return true;
         }
+        "packpush" => {
+            match push(words, pack) {
+                Ok(summary) => {
+                    if !summary.is_empty() {
+                        println!("{}", summary);
+                    }
+                }
+                Err(message) => println!("{}", message),
+            }
+            return true;
+        }
+        "packslide" => {
+            let result = slide(words, pack);
+            if result.is_err() {
+                println!("{}", result.err().unwrap());
+            }
+            return true;
+        }
+        "inspect" => {
+            match inspect(words, pack) {
+                Ok(description) => println!("{}", description),
+                Err(message) => println!("{}", message),
+            }
+            return true;
+        }
+        "inspectstore" => {
+            match inspect(words, store) {
+                Ok(description) => println!("{}", description),
+                Err(message) => println!("{}", message),
+            }
+            return true;
+        }
+        "stats" => {
+            println!("store:\n{}", store.stats());
+            println!("pack:\n{}", pack.stats());
+            return true;
+        }
+        "sort" => {
+            let result = sort(words, pack);
+            if result.is_err() {
+                println!("{}", result.err().unwrap());
+            }
+            return true;
+        }
+        "sortstore" => {
+            let result = sort(words, store);
+            if result.is_err() {
+                println!("{}", result.err().unwrap());
+            }
+            return true;
+        }
+        "bestload" => {
+            match best_load(words, store, pack) {
+                Ok(summary) => println!("{}", summary),
+                Err(message) => println!("{}", message),
+            }
+            return true;
+        }
+        "undo" => {
+            let result = timeline.undo(store, pack);
+            if result.is_err() {
+                println!("{}", result.err().unwrap());
+            }
+            return true;
+        }
+        "redo" => {
+            let result = timeline.redo(store, pack);
+            if result.is_err() {
+                println!("{}", result.err().unwrap());
+            }
+            return true;
+        }
         _ => {
             println!("Unknown command");
         }
     }
-    return true;
+    true
 }
 
 fn get_user_store() -> Result<Pack, String> {
-    let mut store = Pack::new(10, 10);
+    let mut store = Pack::new(10, 10).with_name_policy(NamePolicy::AutoSuffix);
 
-    let stone = Item::new("stone0", 2, 2, '*');
+    let stone = Item::new("stone", 2, 2, '*')
+        .with_kind("rock")
+        .with_value(1)
+        .with_weight(8)
+        .with_description("A smooth river stone.");
     store.add_item(stone, Loc::new(0, 0))?;
 
-    let torch = Item::new("torch0", 1, 3, '&');
+    let torch = Item::new("torch", 1, 3, '&')
+        .with_kind("tool")
+        .with_tag("light")
+        .with_tag("flammable")
+        .with_value(12)
+        .with_weight(2)
+        .with_description("Burns for about an hour.");
     store.add_item(torch, Loc::new(3, 2))?;
 
-    let matches = Item::new("matches0", 3, 2, '!');
+    let matches = Item::new("matches", 3, 2, '!')
+        .with_kind("tool")
+        .with_tag("flammable")
+        .with_value(8)
+        .with_weight(1)
+        .with_description("A box of dry matches.");
     store.add_item(matches, Loc::new(5, 5))?;
 
-    let hatchet = Item::new("hatchet0", 2, 5, '<');
+    let hatchet = Item::new("hatchet", 2, 5, '<')
+        .with_kind("weapon")
+        .with_value(20)
+        .with_weight(6)
+        .with_description("Chops wood, or worse.");
     store.add_item(hatchet, Loc::new(8, 0))?;
 
     Ok(store)
@@ -161,13 +465,131 @@ fn get_user_store() -> Result<Pack, String> {
 fn main() {
     let mut store = get_user_store().unwrap();
     let mut pack = Pack::new(10, 10);
+    let mut timeline = Timeline::default();
     loop {
         print!(">>> ");
         stdout().flush().unwrap();
 
-        let decision = interact(&mut store, &mut pack);
-        if decision == false {
+        let before = Timeline::mark(&store, &pack);
+        let decision = interact(&mut store, &mut pack, &mut timeline);
+        if !decision {
             break;
         }
+        timeline.record(before, &store, &pack);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::PackedItem;
+    use crate::packs::history::DEFAULT_HISTORY_LIMIT;
+
+    /// Run one REPL command's worth of changes as a step.
+    fn step<F>(timeline: &mut Timeline, store: &mut Pack, pack: &mut Pack, command: F)
+    where
+        F: FnOnce(&mut Pack, &mut Pack) -> Result<(), String>,
+    {
+        let before = Timeline::mark(store, pack);
+        let _ = command(store, pack);
+        timeline.record(before, store, pack);
+    }
+
+    fn carry(src: Loc, dst: Loc) -> [Placement; 1] {
+        [Placement {
+            src,
+            dst,
+            transpose: false,
+        }]
+    }
+
+    #[test]
+    fn step_too_old_for_one_pack_is_undone_in_neither() {
+        let mut store = Pack::new(1, 2);
+        let mut pack = Pack::new(1, 1);
+        let mut timeline = Timeline::default();
+        assert!(store
+            .add_item(Item::new("gem", 1, 1, 'g'), Loc::new(0, 0))
+            .is_ok());
+        assert!(store
+            .add_item(Item::new("rock", 1, 1, '*'), Loc::new(0, 1))
+            .is_ok());
+
+        step(&mut timeline, &mut store, &mut pack, |store, pack| {
+            transfer(store, pack, &carry(Loc::new(0, 0), Loc::new(0, 0)))
+        });
+        // Enough store-only steps to push the transfer out of the store's
+        // history, but not out of the pack's.
+        for i in 0..DEFAULT_HISTORY_LIMIT {
+            let (src, dst) = [(1, 0), (0, 1)][i % 2];
+            step(&mut timeline, &mut store, &mut pack, |store, _| {
+                store
+                    .move_item_at(Loc::new(0, src), Loc::new(0, dst))
+                    .map(|_| ())
+            });
+        }
+        for _ in 0..DEFAULT_HISTORY_LIMIT {
+            assert!(timeline.undo(&mut store, &mut pack).is_ok());
+        }
+
+        assert!(timeline.undo(&mut store, &mut pack).is_err());
+        assert_eq!(pack.grab_item(&Loc::new(0, 0)).unwrap().name(), "gem");
+        assert!(store.grab_item(&Loc::new(0, 0)).is_none());
+        assert!(timeline.redo(&mut store, &mut pack).is_ok());
+    }
+
+    #[test]
+    fn failed_transfer_does_not_lose_what_can_be_redone() {
+        let mut store = Pack::new(1, 2);
+        let mut pack = Pack::new(1, 1);
+        let mut timeline = Timeline::default();
+        assert!(store
+            .add_item(Item::new("gem", 1, 1, 'g'), Loc::new(0, 0))
+            .is_ok());
+        assert!(store
+            .add_item(Item::new("rock", 1, 1, '*'), Loc::new(0, 1))
+            .is_ok());
+        step(&mut timeline, &mut store, &mut pack, |store, pack| {
+            transfer(store, pack, &carry(Loc::new(0, 0), Loc::new(0, 0)))
+        });
+        assert!(timeline.undo(&mut store, &mut pack).is_ok());
+
+        // The rock comes out of the store before the blocked pack refuses
+        // it, then goes back.
+        let blocker = Item::new("coin", 1, 1, 'o');
+        assert!(pack
+            .restore(PackedItem::new(Loc::new(0, 0), blocker))
+            .is_ok());
+        step(&mut timeline, &mut store, &mut pack, |store, pack| {
+            transfer(store, pack, &carry(Loc::new(0, 1), Loc::new(0, 0)))
+        });
+        assert!(pack.evict(Loc::new(0, 0)).is_some());
+
+        assert!(timeline.redo(&mut store, &mut pack).is_ok());
+        assert_eq!(pack.grab_item(&Loc::new(0, 0)).unwrap().name(), "gem");
+        assert!(store.grab_item(&Loc::new(0, 0)).is_none());
+    }
+
+    #[test]
+    fn inspect_describes_the_item_covering_a_cell() {
+        let mut pack = Pack::new(2, 2);
+        assert!(pack
+            .add_item(
+                Item::new("rope", 1, 2, 'r').with_kind("tool"),
+                Loc::new(1, 0)
+            )
+            .is_ok());
+
+        let description = inspect("1 1".split_whitespace(), &pack);
+        assert_eq!(
+            description,
+            Ok(pack.grab_item(&Loc::new(1, 0)).unwrap().describe())
+        );
+        assert!(description.unwrap().starts_with("name: rope\nkind: tool\n"));
+        assert_eq!(
+            inspect("0 0".split_whitespace(), &pack),
+            Err("No item there.".to_string())
+        );
+        assert!(inspect("0".split_whitespace(), &pack).is_err());
     }
 }
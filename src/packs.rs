@@ -1,5 +1,447 @@
 pub mod dense_pack;
+pub mod diff;
+pub mod distribute;
+pub mod events;
+pub mod history;
+pub mod invariants;
+pub mod journal;
+pub mod load;
 pub mod map_pack;
+pub mod merge;
+pub mod naming;
+pub mod persistent_pack;
+pub mod push;
+pub mod render;
+pub mod reserve;
+pub mod settle;
+pub mod slide;
+pub mod solve;
+pub mod sort;
+pub mod space;
+pub mod stats;
+pub mod text;
 
 pub use dense_pack::DensePack;
-pub use map_pack::MapPack;
+pub use diff::{Diff, Snapshot};
+pub use events::{Observers, PackEvent, SubscriptionId};
+pub use history::{Change, History};
+pub use invariants::{InvariantReport, Violation};
+pub use load::{load_best, transfer, LoadOptions, Placement};
+pub use naming::NamePolicy;
+pub use render::{RenderMode, Rendered};
+pub use reserve::{Reservation, ReservationId, Reservations};
+pub use settle::Displacement;
+pub use slide::MoveMode;
+pub use solve::{Solution, SolveOptions};
+pub use sort::{Grouping, SortKey, SortOrder, SortStrategy};
+pub use space::Fit;
+pub use stats::PackStats;
+
+use crate::behavior::Motion;
+use crate::items::{Direction, Item, Loc, PackedItem, Rect};
+
+use std::time::Duration;
+
+/// A boxed iterator over packed items borrowed from a pack.
+pub type Items<'a> = Box<dyn Iterator<Item = &'a PackedItem> + 'a>;
+
+/// A boxed iterator over rectangles of a pack.
+pub type Rects<'a> = Box<dyn Iterator<Item = Rect> + 'a>;
+
+/// Read access shared by every pack representation. Everything that only
+/// needs to look at a pack's grid (rendering, queries) is written against
+/// this trait so it works for `DensePack` and `MapPack` alike.
+pub trait Pack {
+    fn rows(&self) -> u32;
+
+    fn cols(&self) -> u32;
+
+    /// Every packed item, in no particular order.
+    fn packed_items(&self) -> Items<'_>;
+
+    /// The packed item covering `loc`, if any.
+    fn item_at(&self, loc: &Loc) -> Option<&PackedItem>;
+
+    /// Every packed item in reading order of their anchors: top to bottom,
+    /// then left to right.
+    fn iter(&self) -> Items<'_> {
+        let mut items: Vec<&PackedItem> = self.packed_items().collect();
+        items.sort_by_key(|packed_item| packed_item.loc());
+        Box::new(items.into_iter())
+    }
+
+    /// Every packed item covering at least one cell of `rect`.
+    fn items_in(&self, rect: Rect) -> Items<'_> {
+        Box::new(
+            self.packed_items()
+                .filter(move |packed_item| packed_item.rect().intersects(&rect)),
+        )
+    }
+
+    /// Every packed item covering a cell of the given row.
+    fn items_in_row(&self, row: u32) -> Items<'_> {
+        self.items_in(Rect::new(Loc::new(row, 0), 1, self.cols()))
+    }
+
+    /// Every packed item covering a cell of the given column.
+    fn items_in_col(&self, col: u32) -> Items<'_> {
+        self.items_in(Rect::new(Loc::new(0, col), self.rows(), 1))
+    }
+
+    /// Every packed item sharing an edge with `item`. Items that only touch
+    /// it at a corner are not adjacent.
+    fn adjacent_items<'a>(&'a self, item: &PackedItem) -> Items<'a> {
+        let mut strips = Vec::new();
+        if item.row() > 0 {
+            strips.push(Rect::new(
+                Loc::new(item.row() - 1, item.col()),
+                1,
+                item.cols(),
+            ));
+        }
+        if item.col() > 0 {
+            strips.push(Rect::new(
+                Loc::new(item.row(), item.col() - 1),
+                item.rows(),
+                1,
+            ));
+        }
+        strips.push(Rect::new(
+            Loc::new(item.row() + item.rows(), item.col()),
+            1,
+            item.cols(),
+        ));
+        strips.push(Rect::new(
+            Loc::new(item.row(), item.col() + item.cols()),
+            item.rows(),
+            1,
+        ));
+
+        let mut adjacent: Vec<&PackedItem> = Vec::new();
+        for strip in strips {
+            for packed_item in self.items_in(strip) {
+                if !adjacent.iter().any(|seen| seen.loc() == packed_item.loc()) {
+                    adjacent.push(packed_item);
+                }
+            }
+        }
+        adjacent.sort_by_key(|packed_item| packed_item.loc());
+        Box::new(adjacent.into_iter())
+    }
+
+    /// Whether `candidate` lies entirely within the pack.
+    fn placement_in_bounds(&self, candidate: &PackedItem) -> bool {
+        candidate.row() < self.rows()
+            && candidate.col() < self.cols()
+            && candidate.row() + candidate.rows() <= self.rows()
+            && candidate.col() + candidate.cols() <= self.cols()
+    }
+
+    /// The cells held for pending items, which nothing else may cover.
+    fn reserved(&self) -> Rects<'_> {
+        Box::new(std::iter::empty())
+    }
+
+    /// The placement rule every pack enforces: `candidate` lies within the
+    /// pack and covers no reserved cell and no packed item other than those
+    /// anchored at `moving`, which are the items being placed anew. Items
+    /// are never skipped for being equal to the candidate.
+    fn placement_is_valid_moving(&self, candidate: &PackedItem, moving: &[Loc]) -> bool {
+        self.placement_in_bounds(candidate)
+            && !self.items_in(candidate.rect()).any(|packed_item| {
+                !moving.contains(&packed_item.loc()) && packed_item.intersects(candidate)
+            })
+            && !self
+                .reserved()
+                .any(|rect| rect.intersects(&candidate.rect()))
+    }
+
+    /// Whether `candidate` could be added: it lies within the pack without
+    /// overlapping any packed item, even an equal one, or any reserved cell.
+    /// Use `motion_is_valid` for an item already in the pack.
+    fn placement_is_valid(&self, candidate: &PackedItem) -> bool {
+        self.placement_is_valid_moving(candidate, &[])
+    }
+
+    /// Whether the packed `item` could make `motion` without leaving the
+    /// pack or overlapping any other item. Behavior hooks are not consulted.
+    fn motion_is_valid(&self, item: &PackedItem, motion: Motion) -> bool {
+        let mut moved = item.clone();
+        moved.move_to(motion.dst);
+        if motion.transpose {
+            moved.transpose();
+        }
+        self.placement_is_valid_moving(&moved, &[item.loc()])
+    }
+
+    /// The one-cell steps that slide the item covering `loc` until it is
+    /// anchored at `dst`, each through a valid position, or None if it
+    /// cannot get there. Found breadth-first, so no path is shorter.
+    /// Behavior hooks are not consulted.
+    fn path_to(&self, loc: Loc, dst: Loc) -> Option<Vec<Direction>> {
+        slide::path_to(self, loc, dst)
+    }
+
+    /// The maximal empty rectangles of the pack.
+    fn free_rectangles(&self) -> Vec<Rect> {
+        space::free_rectangles(self)
+    }
+
+    /// Every anchor where a `rows` x `cols` item could be added, upright or
+    /// transposed.
+    fn fit_positions(&self, rows: u32, cols: u32) -> Vec<Fit> {
+        space::fit_positions(self, rows, cols)
+    }
+
+    /// Decide whether all of `items` fit in the pack's free space together,
+    /// searching exhaustively, and if so where each one goes.
+    fn solve_fit(&self, items: &[Item], options: SolveOptions) -> Solution {
+        solve::solve(self, items, options)
+    }
+
+    /// The combined weight of every packed item.
+    fn total_weight(&self) -> u32 {
+        self.packed_items()
+            .map(|packed_item| packed_item.weight())
+            .sum()
+    }
+
+    /// A copy of the pack's current contents, to compare against later.
+    fn snapshot(&self) -> Snapshot {
+        Snapshot::of(self)
+    }
+
+    /// Fill, fragmentation and tag counts for the pack.
+    fn stats(&self) -> PackStats {
+        stats::stats(self)
+    }
+
+    fn render(&self, mode: RenderMode) -> Rendered<'_, Self>
+    where
+        Self: Sized,
+    {
+        Rendered::new(self, mode)
+    }
+}
+
+/// Mutation shared by every mutable pack representation. Operations run
+/// the items' behavior hooks and leave the pack untouched when they fail.
+/// Items are addressed by any cell they cover.
+pub trait PackMut: Pack {
+    fn add_item(&mut self, item: Item, loc: Loc) -> Result<Loc, String>;
+
+    fn remove_item_at(&mut self, loc: Loc) -> Result<PackedItem, String>;
+
+    fn move_item_at(&mut self, src: Loc, dst: Loc) -> Result<Loc, String>;
+
+    fn transpose_item_at(&mut self, loc: Loc) -> Result<Loc, String>;
+
+    /// The changes that can be undone and redone.
+    fn history(&self) -> &History;
+
+    fn history_mut(&mut self) -> &mut History;
+
+    /// The listeners told about every change that goes through.
+    fn observers_mut(&mut self) -> &mut Observers;
+
+    /// The cells held for pending items.
+    fn reservations(&self) -> &Reservations;
+
+    fn reservations_mut(&mut self) -> &mut Reservations;
+
+    /// Hold the cells of `rect` for the item named `item`, which is on its
+    /// way. Until the reservation is released, fulfilled or, with a `ttl`,
+    /// expires, every other placement treats the cells as occupied. The
+    /// cells must be empty and not reserved already.
+    fn reserve(
+        &mut self,
+        rect: Rect,
+        item: &str,
+        ttl: Option<Duration>,
+    ) -> Result<ReservationId, String> {
+        reserve::reserve(self, rect, item, ttl)
+    }
+
+    /// Give up a reservation. Returns whether it was still held.
+    fn release(&mut self, id: ReservationId) -> bool {
+        reserve::release(self, id)
+    }
+
+    /// Add the item a reservation was held for at the reserved cells, with
+    /// the usual hooks. The item must go by the reserved name and cover the
+    /// cells exactly. The reservation is kept if the item cannot be added.
+    fn fulfill(&mut self, id: ReservationId, item: Item) -> Result<Loc, String> {
+        reserve::fulfill(self, id, item)
+    }
+
+    /// Drop every expired reservation. Returns how many there were.
+    fn expire_reservations(&mut self) -> usize {
+        self.reservations_mut().expire()
+    }
+
+    /// Call `listener` with an event for every change to the pack from now
+    /// on, including undo and redo. Nothing is sent for operations that
+    /// fail or are rolled back.
+    fn subscribe(&mut self, listener: Box<dyn FnMut(&PackEvent)>) -> SubscriptionId {
+        self.observers_mut().subscribe(listener)
+    }
+
+    /// Stop calling a listener. Returns whether it was subscribed.
+    fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.observers_mut().unsubscribe(id)
+    }
+
+    /// Apply several motions as one operation: either every item ends up
+    /// where its motion takes it, or nothing moves. Each motion is keyed by
+    /// a cell its item covers before the operation.
+    fn apply_motions(&mut self, motions: &[(Loc, Motion)]) -> Result<(), String>;
+
+    /// Put `item` back exactly as given, skipping behavior hooks, the name
+    /// policy and the history. Meant for undoing operations; the placement
+    /// must still be valid.
+    fn restore(&mut self, item: PackedItem) -> Result<Loc, String>;
+
+    /// Take out the item covering `loc`, skipping behavior hooks and the
+    /// history. Meant for undoing operations.
+    fn evict(&mut self, loc: Loc) -> Option<PackedItem>;
+
+    /// Reverse the most recent change still in the history. Behavior hooks
+    /// are not consulted.
+    fn undo(&mut self) -> Result<(), String> {
+        history::undo(self)
+    }
+
+    /// Make the most recently undone change again.
+    fn redo(&mut self) -> Result<(), String> {
+        history::redo(self)
+    }
+
+    /// Rearrange the items grouped and ordered by `key`, packed according
+    /// to `strategy`. The pack is left unchanged if they do not fit.
+    fn sort_by(&mut self, key: SortKey, strategy: SortStrategy) -> Result<(), String> {
+        let motions = sort::plan(self, key, strategy)?;
+        self.apply_motions(&motions)
+    }
+
+    /// Slide every item as far towards `direction` as it goes without
+    /// rotating, repeating until nothing moves. Items whose behavior vetoes
+    /// the move stay put. Returns every item that moved, in the order they
+    /// first moved. The whole settle is undone in one step.
+    fn settle(&mut self, direction: Direction) -> Vec<Displacement> {
+        settle::settle(self, direction)
+    }
+
+    /// Slide the item covering `loc` one cell at a time, `steps` times
+    /// towards `direction`. Every step is a move of its own, so every
+    /// position on the way has to be valid; if one is not, the item stays
+    /// where it started. Returns the item's new anchor.
+    fn slide(&mut self, loc: Loc, direction: Direction, steps: u32) -> Result<Loc, String> {
+        slide::slide(self, loc, direction, steps)
+    }
+
+    /// Move the item covering `src` so it is anchored at `dst`, pushing any
+    /// items in the way to the nearest spots they fit, and those items' in
+    /// turn. Either everything fits and moves, or nothing does. Returns
+    /// every pushed item.
+    fn move_with_push(&mut self, src: Loc, dst: Loc) -> Result<Vec<Displacement>, String> {
+        push::move_with_push(self, src, dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packs::map_pack::MapPack;
+
+    /// Lays out the same items in a pack of either representation:
+    ///
+    /// ```text
+    /// |a|a|b|
+    /// | |c|b|
+    /// |d| | |
+    /// ```
+    fn fill<P: Pack>(pack: &mut P, add: fn(&mut P, Item, Loc) -> Result<Loc, String>) {
+        assert!(add(pack, Item::new("d", 1, 1, 'd'), Loc::new(2, 0)).is_ok());
+        assert!(add(pack, Item::new("b", 2, 1, 'b'), Loc::new(0, 2)).is_ok());
+        assert!(add(pack, Item::new("c", 1, 1, 'c'), Loc::new(1, 1)).is_ok());
+        assert!(add(pack, Item::new("a", 1, 2, 'a'), Loc::new(0, 0)).is_ok());
+    }
+
+    fn names(items: Items<'_>) -> Vec<&str> {
+        items.map(|packed_item| packed_item.name()).collect()
+    }
+
+    fn check_queries<P: Pack>(pack: &P) {
+        assert_eq!(names(pack.iter()), vec!["a", "b", "c", "d"]);
+
+        let mut in_row = names(pack.items_in_row(1));
+        in_row.sort();
+        assert_eq!(in_row, vec!["b", "c"]);
+
+        let mut in_col = names(pack.items_in_col(0));
+        in_col.sort();
+        assert_eq!(in_col, vec!["a", "d"]);
+
+        let mut in_rect = names(pack.items_in(Rect::new(Loc::new(1, 0), 2, 2)));
+        in_rect.sort();
+        assert_eq!(in_rect, vec!["c", "d"]);
+
+        let c = pack.item_at(&Loc::new(1, 1)).unwrap();
+        assert_eq!(names(pack.adjacent_items(c)), vec!["a", "b"]);
+
+        let d = pack.item_at(&Loc::new(2, 0)).unwrap();
+        assert!(names(pack.adjacent_items(d)).is_empty());
+    }
+
+    #[test]
+    fn dense_pack_region_queries() {
+        let mut pack = DensePack::new(3, 3);
+        fill(&mut pack, DensePack::add_item);
+        check_queries(&pack);
+    }
+
+    #[test]
+    fn map_pack_region_queries() {
+        let mut pack = MapPack::new(3, 3);
+        fill(&mut pack, MapPack::add_item);
+        check_queries(&pack);
+    }
+
+    /// Moving items may take each other's cells, but not the same cells.
+    fn check_rearrangements(pack: &mut dyn PackMut) {
+        let step = |dst| Motion {
+            dst,
+            transpose: false,
+        };
+        let collide = [
+            (Loc::new(1, 1), step(Loc::new(2, 1))),
+            (Loc::new(2, 0), step(Loc::new(2, 1))),
+        ];
+        assert!(pack.apply_motions(&collide).is_err());
+        assert!(pack.item_at(&Loc::new(2, 1)).is_none());
+
+        let trade = [
+            (Loc::new(1, 1), step(Loc::new(2, 0))),
+            (Loc::new(2, 0), step(Loc::new(1, 1))),
+        ];
+        assert!(pack.apply_motions(&trade).is_ok());
+        assert_eq!(pack.item_at(&Loc::new(1, 1)).unwrap().name(), "d");
+        assert_eq!(pack.item_at(&Loc::new(2, 0)).unwrap().name(), "c");
+        assert!(pack.motion_is_valid(pack.item_at(&Loc::new(2, 0)).unwrap(), step(Loc::new(2, 1))));
+        assert!(!pack.motion_is_valid(pack.item_at(&Loc::new(2, 0)).unwrap(), step(Loc::new(1, 1))));
+    }
+
+    #[test]
+    fn dense_pack_rearrangements() {
+        let mut pack = DensePack::new(3, 3);
+        fill(&mut pack, DensePack::add_item);
+        check_rearrangements(&mut pack);
+    }
+
+    #[test]
+    fn map_pack_rearrangements() {
+        let mut pack = MapPack::new(3, 3);
+        fill(&mut pack, MapPack::add_item);
+        check_rearrangements(&mut pack);
+    }
+}
@@ -1,10 +1,20 @@
+use crate::behavior::{Motion, UseOutcome};
 use crate::items::{Item, Loc, PackedItem};
+use crate::packs::{
+    invariants, slide, Change, History, InvariantReport, Items, MoveMode, NamePolicy, Observers,
+    Pack, PackMut, Rects, RenderMode, Reservation, Reservations,
+};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DensePack {
     rows: u32,
     cols: u32,
     items: Vec<PackedItem>,
+    name_policy: NamePolicy,
+    move_mode: MoveMode,
+    history: History,
+    observers: Observers,
+    reservations: Reservations,
 }
 
 impl DensePack {
@@ -21,32 +31,72 @@ impl DensePack {
             rows,
             cols,
             items: Vec::new(),
+            name_policy: NamePolicy::default(),
+            move_mode: MoveMode::default(),
+            history: History::default(),
+            observers: Observers::default(),
+            reservations: Reservations::default(),
         }
     }
 
-    pub fn take_item(&mut self, loc: &Loc) -> Option<Item> {
-        if let Some(idx) = self.grab_item_index_at(loc) {
-            return Some(self.items.swap_remove(idx).unpack());
-        }
-        None
+    pub fn with_name_policy(mut self, name_policy: NamePolicy) -> Self {
+        self.name_policy = name_policy;
+        self
     }
 
-    fn grab_item(&self, loc: &Loc) -> Option<&PackedItem> {
-        for packed_item in &self.items {
-            if packed_item.contains(loc) {
-                return Some(packed_item);
-            }
+    pub fn name_policy(&self) -> NamePolicy {
+        self.name_policy
+    }
+
+    pub fn with_move_mode(mut self, move_mode: MoveMode) -> Self {
+        self.move_mode = move_mode;
+        self
+    }
+
+    pub fn move_mode(&self) -> MoveMode {
+        self.move_mode
+    }
+
+    /// Remember only the last `limit` changes for undo.
+    pub fn with_history_limit(mut self, limit: usize) -> Self {
+        self.history = History::new(limit);
+        self
+    }
+
+    /// Take the item covering `loc` out of the pack, unless its behavior
+    /// refuses removal.
+    pub fn take_item(&mut self, loc: &Loc) -> Result<Item, String> {
+        match self.grab_item_index_at(loc) {
+            Some(idx) => Ok(self.remove_index(idx)?.unpack()),
+            None => Err("No item at the given location".to_string()),
         }
-        None
     }
 
-    fn grab_item_index(&self, name: &str) -> Option<usize> {
-        for (idx, packed_item) in self.items.iter().enumerate() {
-            if packed_item.name() == name {
-                return Some(idx);
-            }
+    pub fn grab_item(&self, loc: &Loc) -> Option<&PackedItem> {
+        self.items
+            .iter()
+            .find(|packed_item| packed_item.contains(loc))
+    }
+
+    fn grab_item_index(&self, name: &str) -> Result<usize, String> {
+        let mut matches = self
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(_, packed_item)| packed_item.name() == name)
+            .map(|(idx, _)| idx);
+
+        match (matches.next(), matches.next()) {
+            (Some(idx), None) => Ok(idx),
+            (Some(_), Some(_)) => Err(format!("More than one item is named {}", name)),
+            (None, _) => Err(format!("No item named {}", name)),
         }
-        None
+    }
+
+    fn name_is_taken(&self, name: &str) -> bool {
+        self.items
+            .iter()
+            .any(|packed_item| packed_item.name() == name)
     }
 
     fn grab_item_index_at(&self, loc: &Loc) -> Option<usize> {
@@ -58,140 +108,324 @@ impl DensePack {
         None
     }
 
-    fn item_placement_exceeds_bounds(&self, item: &PackedItem) -> bool {
-        return item.row() >= self.rows
-            || item.col() >= self.cols
-            || item.row() + item.rows() > self.rows
-            || item.col() + item.cols() > self.cols;
+    /// Verify that every item is in bounds and that no two items, or an
+    /// item and a reservation, overlap.
+    pub fn check_invariants(&self) -> InvariantReport {
+        invariants::check(self)
     }
 
-    fn item_placement_intersects_contents(&self, item: &PackedItem) -> bool {
-        for packed_item in &self.items {
-            if item == packed_item {
-                continue;
-            }
-            if item.intersects(&packed_item) {
-                return true;
-            }
+    /// Tell the listeners about a change that went through, and remember it
+    /// for undo.
+    fn commit(&mut self, change: Change) {
+        invariants::assert_ok(|| self.check_invariants());
+        self.observers.notify(&change);
+        self.history.record(change);
+    }
+
+    /// Run the on_remove hook of the item at `idx`, then take it out.
+    fn remove_index(&mut self, idx: usize) -> Result<PackedItem, String> {
+        if let Some(behavior) = self.items[idx].behavior() {
+            behavior.on_remove(&self.items[idx], self)?;
         }
-        false
+        let packed_item = self.items.swap_remove(idx);
+        self.commit(Change::Removed(packed_item.clone()));
+        Ok(packed_item)
     }
 
-    fn item_placement_is_invalid(&self, item: &PackedItem) -> bool {
-        return self.item_placement_exceeds_bounds(item)
-            || self.item_placement_intersects_contents(item);
+    /// Run the on_move hook of the item at `idx`, then apply the motion
+    /// unless the resulting placement would be invalid.
+    fn apply_motion(&mut self, idx: usize, motion: Motion) -> Result<Loc, String> {
+        let mut motion = motion;
+        if let Some(behavior) = self.items[idx].behavior() {
+            behavior.on_move(&self.items[idx], &mut motion, self)?;
+        }
+        if !self.motion_is_valid(&self.items[idx], motion) {
+            if motion.transpose {
+                return Err("Invalid transposition.".to_string());
+            }
+            return Err("Invalid move".to_string());
+        }
+
+        let before = self.items[idx].clone();
+        self.items[idx].move_to(motion.dst);
+        if motion.transpose {
+            self.items[idx].transpose();
+        }
+        self.commit(Change::Moved(vec![(before, self.items[idx].clone())]));
+        Ok(self.items[idx].loc())
     }
 
     pub fn add_item(&mut self, item: Item, loc: Loc) -> Result<Loc, String> {
-        let tentative = PackedItem::new(loc.clone(), item);
+        let mut item = item;
+        let mut loc = loc;
+        if let Some(behavior) = item.behavior().cloned() {
+            behavior.on_add(&mut item, &mut loc, self)?;
+        }
+        let name = self
+            .name_policy
+            .resolve(item.name(), |name| self.name_is_taken(name))?;
+        item.rename(&name);
+        let tentative = PackedItem::new(loc, item);
 
         // Invalid loc for this Pack.
-        if self.item_placement_is_invalid(&tentative) {
+        if !self.placement_is_valid(&tentative) {
             return Err("Invalid item placement".to_string());
         }
 
-        self.items.push(tentative);
+        self.items.push(tentative.clone());
+        self.commit(Change::Added(tentative));
         Ok(loc)
     }
 
-    pub fn remove_item(&mut self, name: &str) -> Option<PackedItem> {
-        if let Some(idx) = self.grab_item_index(name) {
-            return Some(self.items.swap_remove(idx));
-        }
-        None
+    /// Remove the item with the given name. Fails when no item, or more
+    /// than one item, goes by that name, or when its behavior refuses
+    /// removal.
+    pub fn remove_item(&mut self, name: &str) -> Result<PackedItem, String> {
+        let idx = self.grab_item_index(name)?;
+        self.remove_index(idx)
     }
 
-    pub fn remove_item_at(&mut self, loc: Loc) -> Option<PackedItem> {
-        if let Some(idx) = self.grab_item_index_at(&loc) {
-            return Some(self.items.swap_remove(idx));
+    pub fn remove_item_at(&mut self, loc: Loc) -> Result<PackedItem, String> {
+        match self.grab_item_index_at(&loc) {
+            Some(idx) => self.remove_index(idx),
+            None => Err("No item at the given location".to_string()),
         }
-        None
     }
 
     pub fn transpose_item(&mut self, name: &str) -> Result<Loc, String> {
-        if let Some(idx) = self.grab_item_index(&name) {
-            // Do the tranposition.
-            self.items[idx].transpose();
-
-            // Undo the transposition if placement is invalid.
-            if self.item_placement_is_invalid(&self.items[idx]) {
-                self.items[idx].transpose();
-                return Err("Invalid transposition.".to_string());
-            }
-            return Ok(self.items[idx].loc());
-        }
-        Err("No item at the given location".to_string())
+        let idx = self.grab_item_index(name)?;
+        let dst = self.items[idx].loc();
+        self.apply_motion(
+            idx,
+            Motion {
+                dst,
+                transpose: true,
+            },
+        )
     }
 
     pub fn transpose_item_at(&mut self, loc: Loc) -> Result<Loc, String> {
         if let Some(idx) = self.grab_item_index_at(&loc) {
-            // Do the tranposition.
-            self.items[idx].transpose();
-
-            // Undo the transposition if placement is invalid.
-            if self.item_placement_is_invalid(&self.items[idx]) {
-                self.items[idx].transpose();
-                return Err("Invalid transposition.".to_string());
-            }
-            return Ok(self.items[idx].loc());
+            let dst = self.items[idx].loc();
+            return self.apply_motion(
+                idx,
+                Motion {
+                    dst,
+                    transpose: true,
+                },
+            );
         }
         Err("No item at the given location".to_string())
     }
 
     pub fn move_item(&mut self, name: &str, dst: Loc) -> Result<Loc, String> {
-        if let Some(idx) = self.grab_item_index(name) {
-            let src = self.items[idx].loc();
+        let idx = self.grab_item_index(name)?;
+        let src = self.items[idx].loc();
+        self.move_item_at(src, dst)?;
+        Ok(src)
+    }
 
-            // Do the move.
-            self.items[idx].move_to(dst);
+    /// Move the item covering `src` so it is anchored at `dst`, straight
+    /// there or cell by cell depending on the pack's move mode.
+    pub fn move_item_at(&mut self, src: Loc, dst: Loc) -> Result<Loc, String> {
+        if self.move_mode == MoveMode::Sliding {
+            let path = match self.path_to(src, dst) {
+                Some(path) => path,
+                None => return Err(format!("The item cannot slide to {}", dst)),
+            };
+            return slide::follow(self, src, &path, DensePack::jump_item_at);
+        }
+        self.jump_item_at(src, dst)
+    }
 
-            // Undo the move if placement is invalid.
-            if self.item_placement_is_invalid(&self.items[idx]) {
-                self.items[idx].move_to(src);
-                return Err("Invalid move".to_string());
-            }
-            return Ok(src);
+    fn jump_item_at(&mut self, src: Loc, dst: Loc) -> Result<Loc, String> {
+        if let Some(idx) = self.grab_item_index_at(&src) {
+            return self.apply_motion(
+                idx,
+                Motion {
+                    dst,
+                    transpose: false,
+                },
+            );
         }
         Err("No item at the given location".to_string())
     }
 
-    pub fn move_item_at(&mut self, src: Loc, dst: Loc) -> Result<Loc, String> {
-        if let Some(idx) = self.grab_item_index_at(&src) {
-            // Do the move.
-            self.items[idx].move_to(dst);
+    /// Use the item covering `loc`. An item its behavior consumes leaves
+    /// the pack.
+    pub fn use_item_at(&mut self, loc: Loc) -> Result<UseOutcome, String> {
+        let idx = match self.grab_item_index_at(&loc) {
+            Some(idx) => idx,
+            None => return Err("No item at the given location".to_string()),
+        };
+        let mut outcome = UseOutcome::Keep;
+        if let Some(behavior) = self.items[idx].behavior() {
+            outcome = behavior.on_use(&self.items[idx], self)?;
+        }
+        if outcome == UseOutcome::Consume {
+            let packed_item = self.items.swap_remove(idx);
+            self.commit(Change::Removed(packed_item));
+        }
+        Ok(outcome)
+    }
+}
+
+impl Pack for DensePack {
+    fn rows(&self) -> u32 {
+        self.rows
+    }
+
+    fn cols(&self) -> u32 {
+        self.cols
+    }
+
+    fn packed_items(&self) -> Items<'_> {
+        Box::new(self.items.iter())
+    }
+
+    fn reserved(&self) -> Rects<'_> {
+        Box::new(self.reservations.active().map(Reservation::rect))
+    }
+
+    fn item_at(&self, loc: &Loc) -> Option<&PackedItem> {
+        self.grab_item(loc)
+    }
+}
+
+impl PackMut for DensePack {
+    fn restore(&mut self, item: PackedItem) -> Result<Loc, String> {
+        if !self.placement_is_valid(&item) {
+            return Err("Invalid item placement".to_string());
+        }
+        let loc = item.loc();
+        self.items.push(item);
+        invariants::assert_ok(|| self.check_invariants());
+        Ok(loc)
+    }
+
+    fn evict(&mut self, loc: Loc) -> Option<PackedItem> {
+        let idx = self.grab_item_index_at(&loc)?;
+        Some(self.items.swap_remove(idx))
+    }
+
+    fn history(&self) -> &History {
+        &self.history
+    }
 
-            // Undo the move if placement is invalid.
-            if self.item_placement_is_invalid(&self.items[idx]) {
-                self.items[idx].move_to(src);
-                return Err("Invalid move".to_string());
+    fn history_mut(&mut self) -> &mut History {
+        &mut self.history
+    }
+
+    fn observers_mut(&mut self) -> &mut Observers {
+        &mut self.observers
+    }
+
+    fn reservations(&self) -> &Reservations {
+        &self.reservations
+    }
+
+    fn reservations_mut(&mut self) -> &mut Reservations {
+        &mut self.reservations
+    }
+
+    fn add_item(&mut self, item: Item, loc: Loc) -> Result<Loc, String> {
+        DensePack::add_item(self, item, loc)
+    }
+
+    fn remove_item_at(&mut self, loc: Loc) -> Result<PackedItem, String> {
+        DensePack::remove_item_at(self, loc)
+    }
+
+    fn move_item_at(&mut self, src: Loc, dst: Loc) -> Result<Loc, String> {
+        DensePack::move_item_at(self, src, dst)
+    }
+
+    fn transpose_item_at(&mut self, loc: Loc) -> Result<Loc, String> {
+        DensePack::transpose_item_at(self, loc)
+    }
+
+    fn apply_motions(&mut self, motions: &[(Loc, Motion)]) -> Result<(), String> {
+        // Resolve every motion against the pack as it is now.
+        let mut planned: Vec<(usize, Motion)> = Vec::new();
+        for (src, motion) in motions {
+            let idx = match self.grab_item_index_at(src) {
+                Some(idx) => idx,
+                None => return Err("No item at the given location".to_string()),
+            };
+            if planned.iter().any(|(planned_idx, _)| *planned_idx == idx) {
+                return Err("More than one motion for the same item".to_string());
+            }
+            let mut motion = *motion;
+            if let Some(behavior) = self.items[idx].behavior() {
+                behavior.on_move(&self.items[idx], &mut motion, self)?;
             }
-            return Ok(self.items[idx].loc());
+            planned.push((idx, motion));
         }
-        Err("No item at the given location".to_string())
+
+        // Check every new placement against the items staying put and
+        // against each other, then do the motions.
+        let anchors: Vec<Loc> = planned
+            .iter()
+            .map(|(idx, _)| self.items[*idx].loc())
+            .collect();
+        let mut moved: Vec<(PackedItem, PackedItem)> = Vec::new();
+        for (idx, motion) in &planned {
+            let original = self.items[*idx].clone();
+            let mut packed_item = original.clone();
+            packed_item.move_to(motion.dst);
+            if motion.transpose {
+                packed_item.transpose();
+            }
+            if !self.placement_is_valid_moving(&packed_item, &anchors)
+                || moved
+                    .iter()
+                    .any(|(_, other)| other.intersects(&packed_item))
+            {
+                return Err("Invalid rearrangement".to_string());
+            }
+            moved.push((original, packed_item));
+        }
+        for ((idx, _), (_, packed_item)) in planned.iter().zip(&moved) {
+            self.items[*idx] = packed_item.clone();
+        }
+        self.commit(Change::Moved(moved));
+        Ok(())
     }
 }
 
 use std::fmt;
+use std::hash::{Hash, Hasher};
 impl fmt::Display for DensePack {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        const SEP: char = '|';
-        for r in 0..self.rows {
-            for c in 0..self.cols {
-                let mut next_symbol: char = ' ';
-                if let Some(packed_item) = self.grab_item(&Loc::new(r, c)) {
-                    next_symbol = packed_item.symbol();
-                }
-                write!(f, "{}{}", SEP, next_symbol)?;
-            }
-            write!(f, "{}\n", SEP)?;
+        self.render(RenderMode::Plain).fmt(f)
+    }
+}
+
+/// Two packs are equal when they are the same size and hold equal items at
+/// the same places, whatever order the items went in. Settings, history,
+/// listeners and reservations are not compared.
+impl PartialEq for DensePack {
+    fn eq(&self, other: &Self) -> bool {
+        (self.rows, self.cols) == (other.rows, other.cols) && self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for DensePack {}
+
+impl Hash for DensePack {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.rows, self.cols).hash(state);
+        for packed_item in self.iter() {
+            packed_item.hash(state);
         }
-        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::items::Orientation;
+    use crate::packs::Violation;
 
     #[test]
     fn add_1x1_item_to_1x1_pack() {
@@ -245,6 +479,21 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn transpose_item_flips_orientation() {
+        let mut pack = DensePack::new(3, 3);
+        let stick = Item::new("stick", 1, 3, '*');
+
+        let result = pack.add_item(stick, Loc::new(0, 0));
+        assert!(result.is_ok());
+
+        let result = pack.transpose_item("stick");
+        assert!(result.is_ok());
+
+        let packed_stick = pack.grab_item(&Loc::new(2, 0)).unwrap();
+        assert_eq!(packed_stick.orientation(), Orientation::Transposed);
+    }
+
     #[test]
     fn transpose_item_causing_intersection_is_an_error() {
         let mut pack = DensePack::new(3, 3);
@@ -262,14 +511,17 @@ mod tests {
     }
 
     #[test]
-    fn remove_item_from_unoccupied_space_returns_none() {
+    fn remove_item_from_unoccupied_space_is_an_error() {
         let mut pack = DensePack::new(3, 3);
         let removed = pack.remove_item_at(Loc::new(0, 0));
-        assert!(removed.is_none());
+        assert_eq!(
+            removed.err(),
+            Some("No item at the given location".to_string())
+        );
     }
 
     #[test]
-    fn remove_item_at_occupied_location_returns_some_packed_item() {
+    fn remove_item_at_occupied_location_returns_the_packed_item() {
         let mut pack = DensePack::new(3, 3);
         let stone = Item::new("stone", 1, 1, '*');
 
@@ -277,7 +529,7 @@ mod tests {
         assert!(result.is_ok());
 
         let removed = pack.remove_item_at(result.unwrap());
-        assert!(matches!(removed, stone));
+        assert_eq!(removed.map(PackedItem::unpack), Ok(stone));
     }
 
     #[test]
@@ -291,4 +543,115 @@ mod tests {
         let result = pack.move_item_at(result.unwrap(), Loc::new(5, 5));
         assert!(result.is_err());
     }
+
+    #[test]
+    fn add_item_with_duplicate_name_is_an_error_when_rejecting_duplicates() {
+        let mut pack = DensePack::new(1, 2).with_name_policy(NamePolicy::RejectDuplicates);
+
+        let result = pack.add_item(Item::new("stone", 1, 1, '*'), Loc::new(0, 0));
+        assert!(result.is_ok());
+
+        let result = pack.add_item(Item::new("stone", 1, 1, '*'), Loc::new(0, 1));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn add_item_with_duplicate_name_is_suffixed_when_auto_suffixing() {
+        let mut pack = DensePack::new(1, 3).with_name_policy(NamePolicy::AutoSuffix);
+
+        for c in 0..3 {
+            let result = pack.add_item(Item::new("torch", 1, 1, '&'), Loc::new(0, c));
+            assert!(result.is_ok());
+        }
+
+        assert_eq!(pack.grab_item(&Loc::new(0, 0)).unwrap().name(), "torch");
+        assert_eq!(pack.grab_item(&Loc::new(0, 1)).unwrap().name(), "torch#2");
+        assert_eq!(pack.grab_item(&Loc::new(0, 2)).unwrap().name(), "torch#3");
+    }
+
+    #[test]
+    fn name_based_operations_on_duplicate_names_are_errors() {
+        let mut pack = DensePack::new(3, 3);
+
+        let result = pack.add_item(Item::new("stone", 1, 1, '*'), Loc::new(0, 0));
+        assert!(result.is_ok());
+        let result = pack.add_item(Item::new("stone", 1, 1, '*'), Loc::new(2, 2));
+        assert!(result.is_ok());
+
+        assert!(pack.move_item("stone", Loc::new(1, 1)).is_err());
+        assert!(pack.transpose_item("stone").is_err());
+        assert_eq!(
+            pack.remove_item("stone").err(),
+            Some("More than one item is named stone".to_string())
+        );
+        assert_eq!(pack.items.len(), 2);
+    }
+
+    #[test]
+    fn check_invariants_reports_overlaps_and_items_out_of_bounds() {
+        let mut pack = DensePack::new(2, 2);
+        let result = pack.add_item(Item::new("rope", 1, 2, 'r'), Loc::new(0, 0));
+        assert!(result.is_ok());
+        assert!(pack.check_invariants().is_ok());
+
+        // Slip items past every check, as a bug would.
+        let coin = PackedItem::new(Loc::new(0, 1), Item::new("coin", 1, 1, 'o'));
+        let pole = PackedItem::new(Loc::new(1, 1), Item::new("pole", 2, 1, 'p'));
+        pack.items.push(coin);
+        pack.items.push(pole);
+
+        let report = pack.check_invariants();
+        assert_eq!(report.items_checked, 3);
+        assert!(matches!(
+            &report.violations[..],
+            [Violation::Overlap(rope, coin), Violation::OutOfBounds(pole)]
+                if rope.name() == "rope" && coin.name() == "coin" && pole.name() == "pole"
+        ));
+    }
+
+    fn hash_of<T: Hash>(value: &T) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn packs_holding_the_same_items_are_equal_whatever_the_insertion_order() {
+        let mut first = DensePack::new(2, 2);
+        let mut second = DensePack::new(2, 2);
+        for (name, loc) in [
+            ("a", Loc::new(0, 0)),
+            ("b", Loc::new(0, 1)),
+            ("c", Loc::new(1, 0)),
+        ] {
+            assert!(first.add_item(Item::new(name, 1, 1, '*'), loc).is_ok());
+        }
+        for (name, loc) in [("b", Loc::new(0, 1)), ("c", Loc::new(1, 0))] {
+            assert!(second.add_item(Item::new(name, 1, 1, '*'), loc).is_ok());
+        }
+        // Removing "a" swaps "c" into its slot of the item list.
+        assert!(first.remove_item("a").is_ok());
+
+        assert_ne!(first.items, second.items);
+        assert_eq!(first, second);
+        assert_eq!(hash_of(&first), hash_of(&second));
+        assert!(second.move_item("b", Loc::new(1, 1)).is_ok());
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn clones_are_independent_and_start_without_listeners() {
+        let mut pack = DensePack::new(1, 2);
+        assert!(pack
+            .add_item(Item::new("coin", 1, 1, 'o'), Loc::new(0, 0))
+            .is_ok());
+        pack.subscribe(Box::new(|_| panic!("the original's listener")));
+
+        let mut preview = pack.clone();
+        assert_eq!(preview, pack);
+        assert!(preview.move_item("coin", Loc::new(0, 1)).is_ok());
+        assert_ne!(preview, pack);
+        assert!(preview.undo().is_ok());
+        assert_eq!(preview, pack);
+    }
 }
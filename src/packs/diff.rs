@@ -0,0 +1,389 @@
+use crate::items::{Color, Loc, PackedItem};
+use crate::packs::render::{Cell, Grid};
+use crate::packs::{Items, Pack, PackMut};
+
+use std::fmt;
+
+/// A frozen copy of a pack's grid and contents. Snapshots are packs in
+/// their own right, so they can be queried and rendered like the original.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Snapshot {
+    rows: u32,
+    cols: u32,
+    /// In reading order of their anchors.
+    items: Vec<PackedItem>,
+}
+
+impl Snapshot {
+    pub(crate) fn of<P: Pack + ?Sized>(pack: &P) -> Self {
+        Snapshot {
+            rows: pack.rows(),
+            cols: pack.cols(),
+            items: pack.iter().cloned().collect(),
+        }
+    }
+
+    pub(crate) fn from_items(rows: u32, cols: u32, mut items: Vec<PackedItem>) -> Self {
+        items.sort_by_key(|packed_item| packed_item.loc());
+        Snapshot { rows, cols, items }
+    }
+
+    /// Build a real pack holding the snapshot's items, creating the empty
+    /// pack with `new`. Items are restored as they were, without hooks.
+    pub fn to_pack<P, F>(&self, new: F) -> Result<P, String>
+    where
+        P: PackMut,
+        F: FnOnce(u32, u32) -> P,
+    {
+        let mut pack = new(self.rows, self.cols);
+        for packed_item in &self.items {
+            pack.restore(packed_item.clone())?;
+        }
+        Ok(pack)
+    }
+
+    /// What changed between this snapshot and a later one. Items are told
+    /// apart by name; of several items sharing a name, one that stayed put
+    /// is matched first, then the nearest.
+    pub fn diff(&self, after: &Snapshot) -> Diff {
+        let mut before: Vec<Option<&PackedItem>> = self.items.iter().map(Some).collect();
+        let mut unmatched: Vec<&PackedItem> = Vec::new();
+        let mut pairs: Vec<(&PackedItem, &PackedItem)> = Vec::new();
+
+        // Items that stayed put first, so they are never paired elsewhere.
+        let mut later: Vec<&PackedItem> = Vec::new();
+        for new in &after.items {
+            let same = before.iter_mut().find(|old| {
+                old.is_some_and(|old| old.name() == new.name() && old.loc() == new.loc())
+            });
+            match same {
+                Some(old) => pairs.push((old.take().unwrap(), new)),
+                None => later.push(new),
+            }
+        }
+        for new in later {
+            let distance =
+                |old: &PackedItem| old.row().abs_diff(new.row()) + old.col().abs_diff(new.col());
+            let nearest = before
+                .iter_mut()
+                .filter(|old| old.is_some_and(|old| old.name() == new.name()))
+                .min_by_key(|old| distance(old.unwrap()));
+            match nearest {
+                Some(old) => pairs.push((old.take().unwrap(), new)),
+                None => unmatched.push(new),
+            }
+        }
+
+        let mut diff = Diff {
+            added: unmatched.into_iter().cloned().collect(),
+            removed: before.into_iter().flatten().cloned().collect(),
+            moved: Vec::new(),
+            reoriented: Vec::new(),
+            after: after.clone(),
+            before: self.clone(),
+        };
+        for (old, new) in pairs {
+            let relocation = Relocation {
+                before: old.clone(),
+                after: new.clone(),
+            };
+            if old.loc() != new.loc() {
+                diff.moved.push(relocation.clone());
+            }
+            if old.orientation() != new.orientation() {
+                diff.reoriented.push(relocation);
+            }
+        }
+        diff.added.sort_by_key(|packed_item| packed_item.loc());
+        diff.moved.sort_by_key(|relocation| relocation.new_loc());
+        diff.reoriented
+            .sort_by_key(|relocation| relocation.new_loc());
+        diff
+    }
+}
+
+impl Pack for Snapshot {
+    fn rows(&self) -> u32 {
+        self.rows
+    }
+
+    fn cols(&self) -> u32 {
+        self.cols
+    }
+
+    fn packed_items(&self) -> Items<'_> {
+        Box::new(self.items.iter())
+    }
+
+    fn item_at(&self, loc: &Loc) -> Option<&PackedItem> {
+        self.items
+            .iter()
+            .find(|packed_item| packed_item.contains(loc))
+    }
+
+    fn iter(&self) -> Items<'_> {
+        Box::new(self.items.iter())
+    }
+}
+
+/// One item as it was and as it is.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Relocation {
+    pub before: PackedItem,
+    pub after: PackedItem,
+}
+
+impl Relocation {
+    pub fn name(&self) -> &str {
+        self.after.name()
+    }
+
+    pub fn old_loc(&self) -> Loc {
+        self.before.loc()
+    }
+
+    pub fn new_loc(&self) -> Loc {
+        self.after.loc()
+    }
+}
+
+/// The differences between two snapshots. An item that was both moved and
+/// reoriented is listed under both.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Diff {
+    pub added: Vec<PackedItem>,
+    pub removed: Vec<PackedItem>,
+    pub moved: Vec<Relocation>,
+    pub reoriented: Vec<Relocation>,
+    before: Snapshot,
+    after: Snapshot,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.moved.is_empty()
+            && self.reoriented.is_empty()
+    }
+
+    /// Draw the later state with every changed cell highlighted.
+    pub fn render(&self) -> RenderedDiff<'_> {
+        RenderedDiff {
+            diff: self,
+            color: false,
+        }
+    }
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for packed_item in &self.added {
+            writeln!(f, "+ {} at {}", packed_item.name(), packed_item.loc())?;
+        }
+        for packed_item in &self.removed {
+            writeln!(f, "- {} from {}", packed_item.name(), packed_item.loc())?;
+        }
+        for relocation in &self.moved {
+            writeln!(
+                f,
+                "~ {} moved {} -> {}",
+                relocation.name(),
+                relocation.old_loc(),
+                relocation.new_loc()
+            )?;
+        }
+        for relocation in &self.reoriented {
+            writeln!(
+                f,
+                "~ {} turned {} -> {}",
+                relocation.name(),
+                relocation.before.orientation(),
+                relocation.after.orientation()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// How a cell changed between the two snapshots.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Mark {
+    Same,
+    Added,
+    Changed,
+    /// Covered before, empty now.
+    Vacated,
+}
+
+impl Mark {
+    fn marker(self) -> char {
+        match self {
+            Mark::Same => ' ',
+            Mark::Added => '+',
+            Mark::Changed => '~',
+            Mark::Vacated => '-',
+        }
+    }
+
+    fn highlight(self) -> Option<Color> {
+        match self {
+            Mark::Same => None,
+            Mark::Added => Some(Color::Green),
+            Mark::Changed => Some(Color::Yellow),
+            Mark::Vacated => Some(Color::Red),
+        }
+    }
+}
+
+/// A diff drawn over the later state's grid, in the plain style. Each
+/// symbol is followed by a marker: `+` added, `~` moved or reoriented, `-`
+/// emptied. With color turned on, changed cells are highlighted as well.
+pub struct RenderedDiff<'a> {
+    diff: &'a Diff,
+    color: bool,
+}
+
+impl RenderedDiff<'_> {
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+}
+
+impl fmt::Display for RenderedDiff<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let after = &self.diff.after;
+        let cell = |symbol, mark: Mark| Cell {
+            bg: mark.highlight(),
+            marker: Some(mark.marker()),
+            ..Cell::blank(symbol)
+        };
+        let mut grid = Grid::new(after.rows(), after.cols(), cell(" ", Mark::Same));
+        // Sized for both states, so renders of either line up.
+        for packed_item in self.diff.before.iter() {
+            grid.fit(packed_item.symbol());
+        }
+
+        // Old spots first, so whatever covers them now paints over.
+        for packed_item in &self.diff.removed {
+            grid.paint(packed_item.rect(), cell(" ", Mark::Vacated));
+        }
+        for relocation in self.diff.moved.iter().chain(&self.diff.reoriented) {
+            grid.paint(relocation.before.rect(), cell(" ", Mark::Vacated));
+        }
+        for packed_item in after.iter() {
+            grid.paint(packed_item.rect(), cell(packed_item.symbol(), Mark::Same));
+        }
+        for packed_item in &self.diff.added {
+            grid.paint(packed_item.rect(), cell(packed_item.symbol(), Mark::Added));
+        }
+        for relocation in self.diff.moved.iter().chain(&self.diff.reoriented) {
+            let after = &relocation.after;
+            grid.paint(after.rect(), cell(after.symbol(), Mark::Changed));
+        }
+        grid.fmt_plain(f, self.color)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::{Item, Orientation};
+    use crate::packs::{map_pack::MapPack, DensePack};
+
+    #[test]
+    fn diff_lists_every_kind_of_change() {
+        let mut pack = DensePack::new(2, 4);
+        for (name, rows, cols, col) in [("rope", 1, 2, 0), ("coin", 1, 1, 2), ("gem", 1, 1, 3)] {
+            assert!(pack
+                .add_item(Item::new(name, rows, cols, '*'), Loc::new(0, col))
+                .is_ok());
+        }
+        let before = pack.snapshot();
+
+        assert!(pack.transpose_item("rope").is_ok());
+        assert!(pack.move_item("coin", Loc::new(1, 2)).is_ok());
+        assert!(pack.remove_item("gem").is_ok());
+        assert!(pack
+            .add_item(Item::new("apple", 1, 1, 'a'), Loc::new(0, 1))
+            .is_ok());
+        let diff = before.diff(&pack.snapshot());
+
+        let names = |items: &[PackedItem]| -> Vec<String> {
+            items.iter().map(|item| item.name().to_string()).collect()
+        };
+        assert_eq!(names(&diff.added), vec!["apple"]);
+        assert_eq!(names(&diff.removed), vec!["gem"]);
+        assert_eq!(diff.moved.len(), 1);
+        assert_eq!(diff.moved[0].name(), "coin");
+        assert_eq!(
+            (diff.moved[0].old_loc(), diff.moved[0].new_loc()),
+            (Loc::new(0, 2), Loc::new(1, 2))
+        );
+        assert_eq!(diff.reoriented.len(), 1);
+        assert_eq!(
+            diff.reoriented[0].after.orientation(),
+            Orientation::Transposed
+        );
+        assert!(before.diff(&before).is_empty());
+    }
+
+    #[test]
+    fn duplicate_names_pair_with_the_item_that_stayed_then_the_nearest() {
+        let mut pack = MapPack::new(1, 5);
+        assert!(pack
+            .add_item(Item::new("coin", 1, 1, 'o'), Loc::new(0, 0))
+            .is_ok());
+        assert!(pack
+            .add_item(Item::new("coin", 1, 1, 'o'), Loc::new(0, 3))
+            .is_ok());
+        let before = pack.snapshot();
+
+        assert!(pack.move_item(Loc::new(0, 3), Loc::new(0, 4)).is_ok());
+        let diff = before.diff(&pack.snapshot());
+        assert_eq!(diff.moved.len(), 1);
+        assert_eq!(diff.moved[0].old_loc(), Loc::new(0, 3));
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+    }
+
+    #[test]
+    fn rendered_diff_marks_changed_cells() {
+        let mut pack = DensePack::new(1, 4);
+        assert!(pack
+            .add_item(Item::new("stone", 1, 1, '*'), Loc::new(0, 0))
+            .is_ok());
+        assert!(pack
+            .add_item(Item::new("coin", 1, 1, 'o'), Loc::new(0, 1))
+            .is_ok());
+        let before = pack.snapshot();
+
+        assert!(pack.move_item("coin", Loc::new(0, 3)).is_ok());
+        assert!(pack
+            .add_item(Item::new("apple", 1, 1, 'a'), Loc::new(0, 2))
+            .is_ok());
+        let diff = before.diff(&pack.snapshot());
+
+        let plain = diff.render().with_color(false).to_string();
+        assert_eq!(plain, "|* | -|a+|o~|\n");
+        let colored = diff.render().with_color(true).to_string();
+        assert!(colored.contains("\x1b[42ma+\x1b[0m"));
+        assert_eq!(diff.render().to_string(), plain);
+    }
+
+    #[test]
+    fn rendered_diff_pads_cells_like_the_pack_render() {
+        let mut pack = DensePack::new(1, 2);
+        assert!(pack
+            .add_item(Item::new("lantern", 1, 1, '灯'), Loc::new(0, 0))
+            .is_ok());
+        let before = pack.snapshot();
+        assert!(pack.remove_item("lantern").is_ok());
+        assert!(pack
+            .add_item(Item::new("stone", 1, 1, '*'), Loc::new(0, 1))
+            .is_ok());
+
+        let diff = before.diff(&pack.snapshot());
+        assert_eq!(diff.render().with_color(false).to_string(), "|  -|* +|\n");
+    }
+}
@@ -0,0 +1,205 @@
+use crate::items::{Item, Loc, Orientation};
+use crate::packs::PackMut;
+
+use std::cmp::Reverse;
+
+/// What kinds of item a pack would rather hold. An item matches when its
+/// kind is one of `kinds` or it carries one of `tags`.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Preference {
+    pub kinds: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+impl Preference {
+    fn matches(&self, item: &Item) -> bool {
+        item.kind()
+            .is_some_and(|kind| self.kinds.iter().any(|wanted| wanted == kind))
+            || item
+                .tags()
+                .iter()
+                .any(|tag| self.tags.iter().any(|wanted| wanted == tag))
+    }
+}
+
+/// How `distribute` chooses a pack for each item.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub enum DistributePolicy {
+    /// Heaviest items first, each into the lightest pack it fits in.
+    #[default]
+    BalanceWeight,
+    /// Each item into the first pack it fits in, in the order given.
+    FillFirst,
+    /// One preference per pack, in the same order as the packs. Each item
+    /// goes into the first pack whose preference it matches and, failing
+    /// that, into the first pack it fits in at all. Packs without an entry
+    /// prefer nothing.
+    Preferences(Vec<Preference>),
+}
+
+/// An item `distribute` placed: the name it ended up with, the index of
+/// its pack, and its anchor there.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Placed {
+    pub name: String,
+    pub pack: usize,
+    pub loc: Loc,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Distribution {
+    pub placed: Vec<Placed>,
+    /// Items that fit in no pack, or that every pack's hooks turned away.
+    pub unplaced: Vec<Item>,
+}
+
+/// Try to add a copy of `item` to `pack` at the first spot it fits,
+/// upright before transposed. Returns `None` if there is no such spot or
+/// the add is vetoed.
+fn place(pack: &mut dyn PackMut, item: &Item) -> Option<Loc> {
+    let fit = pack
+        .fit_positions(item.rows(), item.cols())
+        .into_iter()
+        .min_by_key(|fit| (fit.orientation, fit.loc))?;
+    let mut placed = item.clone();
+    if fit.orientation == Orientation::Transposed {
+        placed.transpose();
+    }
+    pack.add_item(placed, fit.loc).ok()
+}
+
+/// Place `items` across `packs` according to `policy`. Larger items are
+/// placed before smaller ones so they are not crowded out, and items that
+/// fit nowhere are handed back in `unplaced`. Items go in through
+/// `add_item`, so behavior hooks and name policies apply.
+pub fn distribute(
+    items: Vec<Item>,
+    packs: &mut [&mut dyn PackMut],
+    policy: &DistributePolicy,
+) -> Distribution {
+    let mut items = items;
+    match policy {
+        DistributePolicy::BalanceWeight => {
+            items.sort_by_key(|item| (Reverse(item.weight()), Reverse(item.rows() * item.cols())))
+        }
+        _ => items.sort_by_key(|item| Reverse(item.rows() * item.cols())),
+    }
+
+    let mut distribution = Distribution::default();
+    for item in items {
+        let mut order: Vec<usize> = (0..packs.len()).collect();
+        match policy {
+            DistributePolicy::BalanceWeight => {
+                order.sort_by_key(|&i| packs[i].total_weight());
+            }
+            DistributePolicy::FillFirst => {}
+            DistributePolicy::Preferences(preferences) => {
+                order.sort_by_key(|&i| {
+                    !preferences
+                        .get(i)
+                        .is_some_and(|preference| preference.matches(&item))
+                });
+            }
+        }
+
+        let placed = order
+            .into_iter()
+            .find_map(|i| place(&mut *packs[i], &item).map(|loc| (i, loc)));
+        match placed {
+            Some((pack, loc)) => {
+                let name = packs[pack]
+                    .item_at(&loc)
+                    .map_or(String::new(), |packed_item| packed_item.name().to_string());
+                distribution.placed.push(Placed { name, pack, loc });
+            }
+            None => distribution.unplaced.push(item),
+        }
+    }
+    distribution
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packs::{map_pack::MapPack, DensePack, Pack};
+
+    #[test]
+    fn balance_weight_evens_out_the_load() {
+        let mut first = DensePack::new(2, 2);
+        let mut second = MapPack::new(2, 2);
+        let items = vec![
+            Item::new("anvil", 1, 1, 'A').with_weight(10),
+            Item::new("hammer", 1, 1, 'h').with_weight(6),
+            Item::new("tongs", 1, 1, 't').with_weight(3),
+            Item::new("nails", 1, 1, 'n').with_weight(2),
+        ];
+
+        let distribution = distribute(
+            items,
+            &mut [&mut first, &mut second],
+            &DistributePolicy::BalanceWeight,
+        );
+        assert!(distribution.unplaced.is_empty());
+        assert_eq!(first.total_weight(), 10);
+        assert_eq!(second.total_weight(), 11);
+    }
+
+    #[test]
+    fn fill_first_spills_over_and_reports_leftovers() {
+        let mut first = MapPack::new(1, 2);
+        let mut second = DensePack::new(1, 1);
+        let items = vec![
+            Item::new("rope", 1, 1, 'r'),
+            Item::new("pole", 2, 1, '|'),
+            Item::new("log", 3, 1, '='),
+            Item::new("coin", 1, 1, 'o'),
+        ];
+
+        let distribution = distribute(
+            items,
+            &mut [&mut first, &mut second],
+            &DistributePolicy::FillFirst,
+        );
+        // The pole only fits transposed; the log fits nowhere.
+        assert_eq!(first.item_at(&Loc::new(0, 1)).unwrap().name(), "pole");
+        assert_eq!(
+            distribution.placed[1],
+            Placed {
+                name: "rope".to_string(),
+                pack: 1,
+                loc: Loc::new(0, 0),
+            }
+        );
+        let unplaced: Vec<&str> = distribution.unplaced.iter().map(Item::name).collect();
+        assert_eq!(unplaced, vec!["log", "coin"]);
+    }
+
+    #[test]
+    fn preferences_steer_items_to_their_packs() {
+        let mut healer = DensePack::new(2, 2);
+        let mut fighter = DensePack::new(2, 2);
+        let items = vec![
+            Item::new("sword", 1, 1, '/').with_kind("weapon"),
+            Item::new("potion", 1, 1, '!').with_tag("healing"),
+            Item::new("bread", 1, 1, 'b'),
+        ];
+        let policy = DistributePolicy::Preferences(vec![
+            Preference {
+                tags: vec!["healing".to_string()],
+                ..Preference::default()
+            },
+            Preference {
+                kinds: vec!["weapon".to_string()],
+                ..Preference::default()
+            },
+        ]);
+
+        let distribution = distribute(items, &mut [&mut healer, &mut fighter], &policy);
+        assert!(distribution.unplaced.is_empty());
+        let names = |pack: &DensePack| -> Vec<String> {
+            pack.iter().map(|item| item.name().to_string()).collect()
+        };
+        assert_eq!(names(&healer), vec!["potion", "bread"]);
+        assert_eq!(names(&fighter), vec!["sword"]);
+    }
+}
@@ -0,0 +1,276 @@
+use crate::items::{Loc, PackedItem};
+use crate::packs::Change;
+
+use std::fmt;
+
+/// Something that happened to a pack's contents. Events are only sent for
+/// operations that went through.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+pub enum PackEvent {
+    ItemAdded {
+        item: PackedItem,
+        loc: Loc,
+    },
+    ItemRemoved {
+        item: PackedItem,
+        loc: Loc,
+    },
+    ItemMoved {
+        item: PackedItem,
+        old: Loc,
+        new: Loc,
+    },
+    /// The item was turned, and possibly moved at the same time.
+    ItemTransposed {
+        item: PackedItem,
+        old: Loc,
+        new: Loc,
+    },
+}
+
+impl PackEvent {
+    /// The events describing `change`, in order.
+    pub(crate) fn from_change(change: &Change) -> Vec<PackEvent> {
+        match change {
+            Change::Added(item) => vec![PackEvent::ItemAdded {
+                item: item.clone(),
+                loc: item.loc(),
+            }],
+            Change::Removed(item) => vec![PackEvent::ItemRemoved {
+                item: item.clone(),
+                loc: item.loc(),
+            }],
+            Change::Moved(motions) => motions
+                .iter()
+                .map(|(before, after)| {
+                    let (item, old, new) = (after.clone(), before.loc(), after.loc());
+                    if before.orientation() != after.orientation() {
+                        PackEvent::ItemTransposed { item, old, new }
+                    } else {
+                        PackEvent::ItemMoved { item, old, new }
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Identifies a subscription so it can be cancelled.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct SubscriptionId(u64);
+
+type Listener = Box<dyn FnMut(&PackEvent)>;
+type ChangeListener = Box<dyn FnMut(&Change)>;
+
+/// The listeners subscribed to a pack. While held, changes are kept back
+/// and only delivered once the operation holding them succeeds.
+#[derive(Default)]
+pub struct Observers {
+    listeners: Vec<(SubscriptionId, Listener)>,
+    change_listeners: Vec<(SubscriptionId, ChangeListener)>,
+    next_id: u64,
+    held: Vec<Change>,
+    holds: usize,
+}
+
+impl Observers {
+    fn next_id(&mut self) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    pub(crate) fn subscribe(&mut self, listener: Listener) -> SubscriptionId {
+        let id = self.next_id();
+        self.listeners.push((id, listener));
+        id
+    }
+
+    /// Listen to whole changes rather than the events they make up, for
+    /// listeners that need to know which events belong together.
+    pub(crate) fn subscribe_changes(&mut self, listener: ChangeListener) -> SubscriptionId {
+        let id = self.next_id();
+        self.change_listeners.push((id, listener));
+        id
+    }
+
+    /// Returns whether the subscription existed.
+    pub(crate) fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let count = self.listeners.len() + self.change_listeners.len();
+        self.listeners.retain(|(listener_id, _)| *listener_id != id);
+        self.change_listeners
+            .retain(|(listener_id, _)| *listener_id != id);
+        self.listeners.len() + self.change_listeners.len() != count
+    }
+
+    pub(crate) fn notify(&mut self, change: &Change) {
+        if self.holds > 0 {
+            self.held.push(change.clone());
+        } else {
+            self.deliver(change);
+        }
+    }
+
+    fn deliver(&mut self, change: &Change) {
+        for (_, listener) in self.change_listeners.iter_mut() {
+            listener(change);
+        }
+        for event in PackEvent::from_change(change) {
+            for (_, listener) in self.listeners.iter_mut() {
+                listener(&event);
+            }
+        }
+    }
+
+    /// Start keeping changes back. Returns a mark to pass to `discard` if
+    /// the operation fails. Holds nest.
+    pub(crate) fn hold(&mut self) -> usize {
+        self.holds += 1;
+        self.held.len()
+    }
+
+    /// The operation succeeded: once the outermost hold is released, every
+    /// change kept back is delivered.
+    pub(crate) fn release(&mut self) {
+        self.holds = self.holds.saturating_sub(1);
+        if self.holds == 0 {
+            for change in std::mem::take(&mut self.held) {
+                self.deliver(&change);
+            }
+        }
+    }
+
+    /// The operation was rolled back: drop the changes kept back since
+    /// `hold` returned `mark`.
+    pub(crate) fn discard(&mut self, mark: usize) {
+        self.held.truncate(mark);
+        self.release();
+    }
+}
+
+/// Listeners cannot be copied, so a cloned pack starts with none.
+impl Clone for Observers {
+    fn clone(&self) -> Self {
+        Observers::default()
+    }
+}
+
+impl fmt::Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let count = self.listeners.len() + self.change_listeners.len();
+        write!(f, "Observers({} listening)", count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::behavior::{Behavior, ItemBehavior};
+    use crate::items::{Direction, Item, Kind};
+    use crate::packs::{map_pack::MapPack, transfer, DensePack, Pack, PackMut, Placement};
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn listen<P: PackMut>(pack: &mut P) -> Rc<RefCell<Vec<PackEvent>>> {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let sink = Rc::clone(&events);
+        pack.subscribe(Box::new(move |event| sink.borrow_mut().push(event.clone())));
+        events
+    }
+
+    #[test]
+    fn each_operation_sends_its_event() {
+        let mut pack = DensePack::new(2, 3);
+        let events = listen(&mut pack);
+
+        assert!(pack
+            .add_item(Item::new("rope", 1, 2, 'r'), Loc::new(0, 0))
+            .is_ok());
+        assert!(pack.move_item_at(Loc::new(0, 0), Loc::new(0, 1)).is_ok());
+        assert!(pack.transpose_item_at(Loc::new(0, 1)).is_ok());
+        assert!(pack.remove_item_at(Loc::new(1, 1)).is_ok());
+
+        let names: Vec<&str> = events
+            .borrow()
+            .iter()
+            .map(|event| match event {
+                PackEvent::ItemAdded { .. } => "added",
+                PackEvent::ItemRemoved { .. } => "removed",
+                PackEvent::ItemMoved { .. } => "moved",
+                PackEvent::ItemTransposed { .. } => "transposed",
+            })
+            .collect();
+        assert_eq!(names, vec!["added", "moved", "transposed", "removed"]);
+        assert!(matches!(
+            &events.borrow()[1],
+            PackEvent::ItemMoved { old, new, .. } if *old == Loc::new(0, 0) && *new == Loc::new(0, 1)
+        ));
+    }
+
+    #[test]
+    fn failed_operations_send_nothing() {
+        let mut pack = MapPack::new(1, 3);
+        assert!(pack
+            .add_item(Item::new("wall", 1, 1, 'w'), Loc::new(0, 2))
+            .is_ok());
+        assert!(pack
+            .add_item(Item::new("slider", 1, 1, 's'), Loc::new(0, 0))
+            .is_ok());
+        let events = listen(&mut pack);
+
+        assert!(pack
+            .add_item(Item::new("rock", 1, 1, '*'), Loc::new(0, 2))
+            .is_err());
+        // The first step succeeds but the second hits the wall, so the
+        // slide is rolled back as a whole.
+        assert!(pack.slide(Loc::new(0, 0), Direction::Right, 2).is_err());
+        assert!(events.borrow().is_empty());
+    }
+
+    struct Stubborn;
+
+    impl ItemBehavior for Stubborn {
+        fn on_add(&self, _item: &mut Item, _loc: &mut Loc, _pack: &dyn Pack) -> Result<(), String> {
+            Err("Not going in.".to_string())
+        }
+    }
+
+    #[test]
+    fn rolled_back_transfer_sends_nothing_and_unsubscribed_listeners_hear_nothing() {
+        let mut store = DensePack::new(1, 2);
+        let mut pack = DensePack::new(1, 2);
+        assert!(store
+            .add_item(Item::new("gem", 1, 1, 'g'), Loc::new(0, 0))
+            .is_ok());
+        // Restoring skips the hook that would keep the idol out of the store.
+        let idol = Item::new("idol", 1, 1, 'i')
+            .with_kind(Kind::new("relic").with_behavior(Behavior::new(Stubborn)));
+        assert!(store.restore(PackedItem::new(Loc::new(0, 1), idol)).is_ok());
+        let store_events = listen(&mut store);
+        let pack_events = listen(&mut pack);
+
+        let placements = [
+            Placement {
+                src: Loc::new(0, 0),
+                dst: Loc::new(0, 0),
+                transpose: false,
+            },
+            Placement {
+                src: Loc::new(0, 1),
+                dst: Loc::new(0, 1),
+                transpose: false,
+            },
+        ];
+        assert!(transfer(&mut store, &mut pack, &placements).is_err());
+        assert!(store_events.borrow().is_empty());
+        assert!(pack_events.borrow().is_empty());
+
+        let id = pack.subscribe(Box::new(|_| panic!("unsubscribed")));
+        assert!(pack.unsubscribe(id));
+        assert!(transfer(&mut store, &mut pack, &placements[..1]).is_ok());
+        assert_eq!(store_events.borrow().len(), 1);
+        assert_eq!(pack_events.borrow().len(), 1);
+    }
+}
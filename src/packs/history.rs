@@ -0,0 +1,315 @@
+use crate::items::PackedItem;
+use crate::packs::PackMut;
+
+use std::collections::VecDeque;
+
+/// How many changes a pack remembers unless told otherwise.
+pub const DEFAULT_HISTORY_LIMIT: usize = 100;
+
+/// One successful mutation of a pack, recorded with enough detail to
+/// reverse it.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Change {
+    Added(PackedItem),
+    Removed(PackedItem),
+    /// Items moved or transposed together, as they were before and after.
+    Moved(Vec<(PackedItem, PackedItem)>),
+}
+
+impl Change {
+    /// The change that takes the pack back to how it was before this one.
+    fn inverse(&self) -> Change {
+        match self {
+            Change::Added(packed_item) => Change::Removed(packed_item.clone()),
+            Change::Removed(packed_item) => Change::Added(packed_item.clone()),
+            Change::Moved(motions) => Change::Moved(
+                motions
+                    .iter()
+                    .map(|(before, after)| (after.clone(), before.clone()))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Make the change again, without behavior hooks. Either all of it
+    /// happens or none of it does.
+    fn apply<P: PackMut + ?Sized>(&self, pack: &mut P) -> Result<(), String> {
+        let (gone, back): (Vec<&PackedItem>, Vec<&PackedItem>) = match self {
+            Change::Added(packed_item) => (Vec::new(), vec![packed_item]),
+            Change::Removed(packed_item) => (vec![packed_item], Vec::new()),
+            Change::Moved(motions) => motions
+                .iter()
+                .map(|(before, after)| (before, after))
+                .unzip(),
+        };
+
+        let mut evicted = Vec::new();
+        for packed_item in gone {
+            match pack.evict(packed_item.loc()) {
+                Some(evicted_item) if evicted_item == *packed_item => evicted.push(evicted_item),
+                other => {
+                    evicted.extend(other);
+                    for packed_item in evicted {
+                        let _ = pack.restore(packed_item);
+                    }
+                    return Err("The pack has changed since.".to_string());
+                }
+            }
+        }
+
+        let mut restored = Vec::new();
+        for packed_item in back {
+            match pack.restore(packed_item.clone()) {
+                Ok(loc) => restored.push(loc),
+                Err(message) => {
+                    for loc in restored {
+                        pack.evict(loc);
+                    }
+                    for packed_item in evicted {
+                        let _ = pack.restore(packed_item);
+                    }
+                    return Err(message);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The undo and redo stacks of a pack. Only the most recent `limit`
+/// changes can be undone.
+#[derive(Debug, Clone)]
+pub struct History {
+    undo: VecDeque<Change>,
+    redo: Vec<Change>,
+    limit: usize,
+    recorded: u64,
+    /// The redo stack a recorded change cleared, and `recorded` just before
+    /// that change, so forgetting the change brings the stack back.
+    cleared_redo: Option<(u64, Vec<Change>)>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::new(DEFAULT_HISTORY_LIMIT)
+    }
+}
+
+impl History {
+    pub fn new(limit: usize) -> Self {
+        History {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            limit,
+            recorded: 0,
+            cleared_redo: None,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// How many changes can be undone in a row.
+    pub fn undo_depth(&self) -> usize {
+        self.undo.len()
+    }
+
+    /// How many changes can be redone in a row.
+    pub fn redo_depth(&self) -> usize {
+        self.redo.len()
+    }
+
+    /// How many changes have been recorded over the pack's life, minus any
+    /// forgotten. Comparing two readings tells how many changes an
+    /// operation made.
+    pub fn recorded(&self) -> u64 {
+        self.recorded
+    }
+
+    /// Remember a change just made. Anything undone before can no longer
+    /// be redone.
+    pub(crate) fn record(&mut self, change: Change) {
+        if !self.redo.is_empty() {
+            self.cleared_redo = Some((self.recorded, std::mem::take(&mut self.redo)));
+        }
+        self.recorded += 1;
+        self.push_undo(change);
+    }
+
+    fn push_undo(&mut self, change: Change) {
+        if self.limit == 0 {
+            return;
+        }
+        if self.undo.len() == self.limit {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(change);
+    }
+
+    /// Drop the changes recorded since `recorded()` read `mark`. Used when
+    /// an operation made of several changes is rolled back by hand. Whatever
+    /// could be redone before the operation can be redone again.
+    pub(crate) fn forget_since(&mut self, mark: u64) {
+        for _ in mark..self.recorded {
+            self.undo.pop_back();
+        }
+        self.recorded = self.recorded.min(mark);
+        if let Some((cleared_at, _)) = self.cleared_redo {
+            if mark <= cleared_at {
+                self.redo = self.cleared_redo.take().unwrap().1;
+            }
+        }
+    }
+
+    /// Merge the moves recorded since `recorded()` read `mark` into one
+    /// change, so an operation made of several steps is undone in one.
+    pub(crate) fn squash_since(&mut self, mark: u64) {
+        let count = self.recorded.saturating_sub(mark) as usize;
+        if count < 2 || count > self.undo.len() {
+            return;
+        }
+        let start = self.undo.len() - count;
+        if !self
+            .undo
+            .range(start..)
+            .all(|change| matches!(change, Change::Moved(_)))
+        {
+            return;
+        }
+
+        let mut motions: Vec<(PackedItem, PackedItem)> = Vec::new();
+        for change in self.undo.split_off(start) {
+            let Change::Moved(steps) = change else {
+                continue;
+            };
+            for (before, after) in steps {
+                // An item moving again keeps where it first came from.
+                match motions.iter_mut().find(|(_, last)| *last == before) {
+                    Some(motion) => motion.1 = after,
+                    None => motions.push((before, after)),
+                }
+            }
+        }
+        self.undo.push_back(Change::Moved(motions));
+        self.recorded = mark + 1;
+        if let Some((cleared_at, _)) = &mut self.cleared_redo {
+            *cleared_at = (*cleared_at).min(mark);
+        }
+    }
+}
+
+pub(crate) fn undo<P: PackMut + ?Sized>(pack: &mut P) -> Result<(), String> {
+    let change = match pack.history_mut().undo.pop_back() {
+        Some(change) => change,
+        None => return Err("Nothing to undo.".to_string()),
+    };
+    let inverse = change.inverse();
+    if let Err(message) = inverse.apply(pack) {
+        pack.history_mut().undo.push_back(change);
+        return Err(message);
+    }
+    pack.observers_mut().notify(&inverse);
+    pack.history_mut().redo.push(change);
+    Ok(())
+}
+
+pub(crate) fn redo<P: PackMut + ?Sized>(pack: &mut P) -> Result<(), String> {
+    let change = match pack.history_mut().redo.pop() {
+        Some(change) => change,
+        None => return Err("Nothing to redo.".to_string()),
+    };
+    if let Err(message) = change.apply(pack) {
+        pack.history_mut().redo.push(change);
+        return Err(message);
+    }
+    pack.observers_mut().notify(&change);
+    pack.history_mut().push_undo(change);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::{Item, Loc};
+    use crate::packs::{map_pack::MapPack, DensePack, Pack};
+
+    #[test]
+    fn every_kind_of_change_can_be_undone_and_redone() {
+        let mut pack = MapPack::new(2, 3);
+        assert!(pack
+            .add_item(Item::new("rope", 1, 2, 'r'), Loc::new(0, 0))
+            .is_ok());
+        assert!(pack
+            .add_item(Item::new("coin", 1, 1, 'o'), Loc::new(1, 2))
+            .is_ok());
+        let added = pack.to_string();
+        assert!(pack.move_item(Loc::new(0, 0), Loc::new(0, 1)).is_ok());
+        assert!(pack.transpose_item(Loc::new(0, 1)).is_ok());
+        let turned = pack.to_string();
+        assert!(pack.remove_item(Loc::new(1, 2)).is_ok());
+        let removed = pack.to_string();
+
+        for _ in 0..3 {
+            assert!(pack.undo().is_ok());
+        }
+        assert_eq!(pack.to_string(), added);
+        assert!(pack.redo().is_ok());
+        assert!(pack.redo().is_ok());
+        assert_eq!(pack.to_string(), turned);
+        assert!(pack.redo().is_ok());
+        assert_eq!(pack.to_string(), removed);
+        assert!(pack.redo().is_err());
+
+        for _ in 0..5 {
+            assert!(pack.undo().is_ok());
+        }
+        assert!(pack.undo().is_err());
+        assert_eq!(pack.iter().count(), 0);
+    }
+
+    #[test]
+    fn new_changes_clear_redo_and_history_is_bounded() {
+        let mut pack = DensePack::new(1, 4).with_history_limit(2);
+        for (name, col) in [("a", 0), ("b", 1), ("c", 2)] {
+            assert!(pack
+                .add_item(Item::new(name, 1, 1, 'x'), Loc::new(0, col))
+                .is_ok());
+        }
+        assert!(pack.undo().is_ok());
+        assert!(pack.history().can_redo());
+        assert!(pack.take_item(&Loc::new(0, 0)).is_ok());
+        assert!(!pack.history().can_redo());
+
+        // Only the last two changes are remembered.
+        assert!(pack.undo().is_ok());
+        assert!(pack.undo().is_ok());
+        assert!(pack.undo().is_err());
+        assert_eq!(pack.to_string(), "|x| | | |\n");
+    }
+
+    #[test]
+    fn sort_is_undone_in_one_step() {
+        let mut pack = DensePack::new(1, 3);
+        assert!(pack
+            .add_item(Item::new("b", 1, 1, 'b'), Loc::new(0, 0))
+            .is_ok());
+        assert!(pack
+            .add_item(Item::new("a", 1, 1, 'a'), Loc::new(0, 2))
+            .is_ok());
+        let before = pack.to_string();
+        let key = crate::packs::SortKey {
+            order: crate::packs::SortOrder::Name,
+            ..Default::default()
+        };
+        assert!(pack
+            .sort_by(key, crate::packs::SortStrategy::RowMajor)
+            .is_ok());
+        assert!(pack.undo().is_ok());
+        assert_eq!(pack.to_string(), before);
+    }
+}
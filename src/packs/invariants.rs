@@ -0,0 +1,188 @@
+use crate::items::{Loc, PackedItem, Rect};
+use crate::packs::Pack;
+
+use std::fmt;
+
+/// One way in which a pack's contents are inconsistent.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Violation {
+    /// The item reaches past the edge of the pack.
+    OutOfBounds(PackedItem),
+    /// Two items cover the same cell.
+    Overlap(PackedItem, PackedItem),
+    /// A `MapPack` holds the item under a key other than its anchor.
+    MisplacedKey { key: Loc, item: PackedItem },
+    /// A reservation covers cells an item is in.
+    ReservedOverItem { reserved: Rect, item: PackedItem },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::OutOfBounds(item) => {
+                write!(f, "{} at {} is out of bounds", item.name(), item.rect())
+            }
+            Violation::Overlap(first, second) => write!(
+                f,
+                "{} at {} overlaps {} at {}",
+                first.name(),
+                first.rect(),
+                second.name(),
+                second.rect()
+            ),
+            Violation::MisplacedKey { key, item } => write!(
+                f,
+                "{} anchored at {} is keyed by {}",
+                item.name(),
+                item.loc(),
+                key
+            ),
+            Violation::ReservedOverItem { reserved, item } => write!(
+                f,
+                "{} is reserved but {} is at {}",
+                reserved,
+                item.name(),
+                item.rect()
+            ),
+        }
+    }
+}
+
+/// What a consistency check of a pack found.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct InvariantReport {
+    pub items_checked: usize,
+    pub violations: Vec<Violation>,
+}
+
+impl InvariantReport {
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+impl fmt::Display for InvariantReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} items checked, {} violations",
+            self.items_checked,
+            self.violations.len()
+        )?;
+        for violation in &self.violations {
+            write!(f, "\n  {}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+/// The checks every pack representation shares: items stay in bounds, do
+/// not overlap each other and are not covered by reservations.
+pub(crate) fn check<P: Pack + ?Sized>(pack: &P) -> InvariantReport {
+    let items: Vec<&PackedItem> = pack.packed_items().collect();
+    let mut report = InvariantReport {
+        items_checked: items.len(),
+        violations: Vec::new(),
+    };
+    for (i, item) in items.iter().enumerate() {
+        if !pack.placement_in_bounds(item) {
+            report
+                .violations
+                .push(Violation::OutOfBounds((*item).clone()));
+        }
+        for other in &items[i + 1..] {
+            if item.intersects(other) {
+                report
+                    .violations
+                    .push(Violation::Overlap((*item).clone(), (*other).clone()));
+            }
+        }
+        for reserved in pack.reserved() {
+            if reserved.intersects(&item.rect()) {
+                report.violations.push(Violation::ReservedOverItem {
+                    reserved,
+                    item: (*item).clone(),
+                });
+            }
+        }
+    }
+    report
+}
+
+/// Panic with the report if `report` found anything. Packs call this after
+/// every mutation in debug and test builds.
+pub(crate) fn assert_ok(report: impl FnOnce() -> InvariantReport) {
+    if cfg!(any(debug_assertions, test)) {
+        let report = report();
+        assert!(report.is_ok(), "pack invariants violated: {}", report);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::Item;
+    use crate::packs::persistent_pack::PersistentPack;
+    use crate::packs::{map_pack::MapPack, DensePack, PackMut};
+
+    #[test]
+    fn consistent_packs_pass() {
+        let mut pack = MapPack::new(2, 3);
+        assert!(pack
+            .add_item(Item::new("rope", 1, 2, 'r'), Loc::new(0, 0))
+            .is_ok());
+        assert!(pack.move_item(Loc::new(0, 0), Loc::new(1, 1)).is_ok());
+        let report = pack.check_invariants();
+        assert!(report.is_ok());
+        assert_eq!(report.items_checked, 1);
+    }
+
+    /// Identical items are allowed by default, so refusing to stack them is
+    /// what keeps the assertion in `commit` from firing. A refused add
+    /// leaves nothing behind to undo.
+    fn stack_twins(pack: &mut dyn PackMut) {
+        let stone = Item::new("stone", 1, 1, '*');
+        assert!(pack.add_item(stone.clone(), Loc::new(0, 0)).is_ok());
+        assert!(pack.add_item(stone.clone(), Loc::new(0, 0)).is_err());
+        assert_eq!(pack.packed_items().count(), 1);
+        assert!(pack.undo().is_ok());
+        assert!(pack.undo().is_err());
+        assert!(pack.redo().is_ok());
+
+        assert!(pack.add_item(stone, Loc::new(0, 1)).is_ok());
+        assert!(pack.move_item_at(Loc::new(0, 1), Loc::new(0, 0)).is_err());
+        assert!(pack.move_item_at(Loc::new(0, 0), Loc::new(1, 0)).is_ok());
+        assert!(pack.move_item_at(Loc::new(1, 0), Loc::new(0, 1)).is_err());
+        assert_eq!(pack.packed_items().count(), 2);
+        assert!(check(pack).is_ok());
+    }
+
+    #[test]
+    fn identical_items_never_trip_the_assertion() {
+        let mut dense = DensePack::new(2, 2);
+        stack_twins(&mut dense);
+        assert!(dense.check_invariants().is_ok());
+        let mut map = MapPack::new(2, 2);
+        stack_twins(&mut map);
+        assert!(map.check_invariants().is_ok());
+
+        // Persistent packs follow the same rule through their own methods.
+        let stone = Item::new("stone", 1, 1, '*');
+        let persistent = PersistentPack::new(2, 2)
+            .add(stone.clone(), Loc::new(0, 0))
+            .unwrap();
+        assert!(persistent.add(stone.clone(), Loc::new(0, 0)).is_err());
+        let persistent = persistent.add(stone, Loc::new(0, 1)).unwrap();
+        assert!(persistent
+            .move_item(Loc::new(0, 1), Loc::new(0, 0))
+            .is_err());
+        let persistent = persistent
+            .move_item(Loc::new(0, 0), Loc::new(1, 0))
+            .unwrap();
+        assert!(persistent
+            .move_item(Loc::new(1, 0), Loc::new(0, 1))
+            .is_err());
+        assert_eq!(persistent.iter().count(), 2);
+        assert!(check(&persistent).is_ok());
+    }
+}
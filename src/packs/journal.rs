@@ -0,0 +1,561 @@
+use crate::items::{Color, Loc, Orientation, PackedItem, SavedItem};
+use crate::packs::{Change, Pack, PackMut, SubscriptionId};
+
+use std::cell::RefCell;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+// Every entry is one line of tab-separated fields ending in a newline:
+//
+//   pack       rows cols generation       (first line of a snapshot only)
+//   generation generation                 (first line of a journal only)
+//   add        row col name rows cols orientation symbol fg bg kind value
+//              weight description tags...
+//   remove     row col
+//   move       old-row old-col new-row new-col turned ...
+//
+// A move lists every item that moved in one operation, five fields each;
+// `turned` is 1 for an item that was also transposed. They are replayed
+// together, since each may move into a spot another one left.
+//
+// Fields escape backslashes, tabs and newlines with a backslash. Missing
+// colors, kinds and descriptions are empty fields. Behaviors are not
+// written, so recovered items come back without them.
+//
+// Each checkpoint starts a new generation: the snapshot is written with it
+// first, then the journal is started over under it. A journal of an older
+// generation than its snapshot is left over from a checkpoint that was cut
+// short, and everything in it is already in the snapshot. A missing
+// generation is 0.
+
+fn escape(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(field: &str) -> Result<String, String> {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => unescaped.push('\\'),
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            _ => return Err(format!("Bad escape in {:?}", field)),
+        }
+    }
+    Ok(unescaped)
+}
+
+fn encode_color(color: Option<Color>) -> String {
+    match color {
+        None => String::new(),
+        Some(Color::Ansi(index)) => format!("ansi:{}", index),
+        Some(Color::Rgb(r, g, b)) => format!("rgb:{},{},{}", r, g, b),
+        Some(color) => color.name().unwrap_or_default().to_string(),
+    }
+}
+
+fn decode_color(field: &str) -> Result<Option<Color>, String> {
+    if field.is_empty() {
+        return Ok(None);
+    }
+    let color = if let Some(color) = Color::named(field) {
+        color
+    } else if let Some(index) = field.strip_prefix("ansi:") {
+        Color::Ansi(number(index)?)
+    } else if let Some(rgb) = field.strip_prefix("rgb:") {
+        let parts: Vec<&str> = rgb.split(',').collect();
+        if parts.len() != 3 {
+            return Err(format!("Bad color {:?}", field));
+        }
+        Color::Rgb(number(parts[0])?, number(parts[1])?, number(parts[2])?)
+    } else {
+        return Err(format!("Bad color {:?}", field));
+    };
+    Ok(Some(color))
+}
+
+fn number<T: std::str::FromStr>(field: &str) -> Result<T, String> {
+    field
+        .parse::<T>()
+        .map_err(|_| format!("Expected a number, found {:?}", field))
+}
+
+fn loc(row: &str, col: &str) -> Result<Loc, String> {
+    Ok(Loc::new(number(row)?, number(col)?))
+}
+
+fn encode_add(packed_item: &PackedItem) -> String {
+    let mut fields = vec![
+        "add".to_string(),
+        packed_item.row().to_string(),
+        packed_item.col().to_string(),
+        escape(packed_item.name()),
+        packed_item.rows().to_string(),
+        packed_item.cols().to_string(),
+        packed_item.orientation().to_string(),
+        escape(packed_item.symbol()),
+        encode_color(packed_item.fg()),
+        encode_color(packed_item.bg()),
+        escape(packed_item.kind().unwrap_or_default()),
+        packed_item.value().to_string(),
+        packed_item.weight().to_string(),
+        escape(packed_item.description().unwrap_or_default()),
+    ];
+    fields.extend(packed_item.tags().iter().map(|tag| escape(tag)));
+    fields.join("\t")
+}
+
+fn decode_add(fields: &[&str]) -> Result<PackedItem, String> {
+    if fields.len() < 14 {
+        return Err("Too few fields for an added item".to_string());
+    }
+    let field = |i: usize| unescape(fields[i]);
+    let optional = |i: usize| field(i).map(|text| Some(text).filter(|text| !text.is_empty()));
+    let orientation = match fields[6] {
+        "upright" => Orientation::Upright,
+        "transposed" => Orientation::Transposed,
+        other => return Err(format!("Bad orientation {:?}", other)),
+    };
+    let item = SavedItem {
+        name: field(3)?,
+        rows: number(fields[4])?,
+        cols: number(fields[5])?,
+        orientation,
+        symbol: field(7)?,
+        fg: decode_color(fields[8])?,
+        bg: decode_color(fields[9])?,
+        kind: optional(10)?,
+        description: optional(13)?,
+        tags: fields[14..]
+            .iter()
+            .map(|tag| unescape(tag))
+            .collect::<Result<_, _>>()?,
+        value: number(fields[11])?,
+        weight: number(fields[12])?,
+    };
+    Ok(PackedItem::new(loc(fields[1], fields[2])?, item.build()))
+}
+
+fn encode_change(change: &Change) -> String {
+    match change {
+        Change::Added(packed_item) => encode_add(packed_item),
+        Change::Removed(packed_item) => {
+            format!("remove\t{}\t{}", packed_item.row(), packed_item.col())
+        }
+        Change::Moved(motions) => {
+            let mut line = "move".to_string();
+            for (before, after) in motions {
+                let turned = before.orientation() != after.orientation();
+                line.push_str(&format!(
+                    "\t{}\t{}\t{}\t{}\t{}",
+                    before.row(),
+                    before.col(),
+                    after.row(),
+                    after.col(),
+                    turned as u8
+                ));
+            }
+            line
+        }
+    }
+}
+
+/// Apply one journal entry, without behavior hooks.
+fn apply_entry<P: PackMut + ?Sized>(pack: &mut P, line: &str) -> Result<(), String> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    match (fields[0], fields.len()) {
+        ("add", _) => pack.restore(decode_add(&fields)?).map(|_| ()),
+        ("remove", 3) => match pack.evict(loc(fields[1], fields[2])?) {
+            Some(_) => Ok(()),
+            None => Err("No item to remove".to_string()),
+        },
+        ("move", n) if n > 1 && (n - 1) % 5 == 0 => {
+            let mut motions = Vec::new();
+            for group in fields[1..].chunks(5) {
+                let turned = match group[4] {
+                    "0" => false,
+                    "1" => true,
+                    other => return Err(format!("Bad turn flag {:?}", other)),
+                };
+                motions.push((loc(group[0], group[1])?, loc(group[2], group[3])?, turned));
+            }
+
+            // Lift every item out before putting any back.
+            let mut lifted = Vec::new();
+            for (old, _, _) in &motions {
+                match pack.evict(*old) {
+                    Some(packed_item) => lifted.push(packed_item),
+                    None => {
+                        for packed_item in lifted {
+                            let _ = pack.restore(packed_item);
+                        }
+                        return Err("No item to move".to_string());
+                    }
+                }
+            }
+            let mut placed = Vec::new();
+            for (packed_item, (_, new, turned)) in lifted.iter().zip(&motions) {
+                let mut moved = packed_item.clone();
+                moved.move_to(*new);
+                if *turned {
+                    moved.transpose();
+                }
+                match pack.restore(moved) {
+                    Ok(loc) => placed.push(loc),
+                    Err(message) => {
+                        for loc in placed {
+                            pack.evict(loc);
+                        }
+                        for packed_item in lifted {
+                            let _ = pack.restore(packed_item);
+                        }
+                        return Err(message);
+                    }
+                }
+            }
+            Ok(())
+        }
+        _ => Err(format!("Unknown entry {:?}", fields[0])),
+    }
+}
+
+/// Replace the file at `path` with `text` through a temporary file, so a
+/// crash leaves either the old file or the new one, never half of it.
+fn replace_file(path: &Path, text: &str) -> io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    fs::write(&temporary, text)?;
+    fs::rename(&temporary, path)
+}
+
+fn write_generation<P: Pack + ?Sized>(pack: &P, path: &Path, generation: u64) -> io::Result<()> {
+    let mut text = format!("pack\t{}\t{}\t{}\n", pack.rows(), pack.cols(), generation);
+    for packed_item in pack.iter() {
+        text.push_str(&encode_add(packed_item));
+        text.push('\n');
+    }
+    replace_file(path, &text)
+}
+
+/// Write every item of `pack` to `path` in the journal's format, through a
+/// temporary file so a crash never leaves half a snapshot behind. The
+/// snapshot is of generation 0, the generation of a new journal.
+pub fn write_snapshot<P: Pack + ?Sized>(pack: &P, path: &Path) -> io::Result<()> {
+    write_generation(pack, path, 0)
+}
+
+/// The generation a journal's text was written under.
+fn journal_generation(text: &str) -> Result<u64, String> {
+    match text
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix("generation\t"))
+    {
+        Some(generation) => number(generation),
+        None => Ok(0),
+    }
+}
+
+/// A pack rebuilt by `recover`.
+#[derive(Debug)]
+pub struct Recovery<P> {
+    pub pack: P,
+    /// How many journal entries were replayed on top of the snapshot.
+    pub replayed: usize,
+    /// How many journal entries were skipped because the snapshot already
+    /// holds them, after a checkpoint cut short by a crash.
+    pub stale: usize,
+    /// A final entry cut off by a crash, if there was one. It is not
+    /// applied.
+    pub truncated: Option<String>,
+}
+
+/// Rebuild a pack from a snapshot and the journal written since, creating
+/// the empty pack with `new`. A missing journal counts as empty, and one
+/// of an older generation than the snapshot is skipped. Every complete
+/// entry must apply; only the final one may be cut short. Checkpoint
+/// before journaling further changes, so they belong to the snapshot's
+/// generation.
+pub fn recover<P, F>(snapshot: &Path, journal: &Path, new: F) -> Result<Recovery<P>, String>
+where
+    P: PackMut,
+    F: FnOnce(u32, u32) -> P,
+{
+    let snapshot_text = fs::read_to_string(snapshot).map_err(|error| error.to_string())?;
+    let journal_text = match fs::read_to_string(journal) {
+        Ok(text) => text,
+        Err(error) if error.kind() == ErrorKind::NotFound => String::new(),
+        Err(error) => return Err(error.to_string()),
+    };
+
+    let mut lines = snapshot_text.lines();
+    let header: Vec<&str> = lines.next().unwrap_or_default().split('\t').collect();
+    let (mut pack, generation) = match header.as_slice() {
+        ["pack", rows, cols] => (new(number(rows)?, number(cols)?), 0),
+        ["pack", rows, cols, generation] => {
+            (new(number(rows)?, number(cols)?), number(generation)?)
+        }
+        _ => return Err("snapshot line 1: expected the pack size".to_string()),
+    };
+    for (i, line) in lines.enumerate() {
+        apply_entry(&mut pack, line)
+            .map_err(|message| format!("snapshot line {}: {}", i + 2, message))?;
+    }
+
+    let journal_generation = journal_generation(&journal_text)
+        .map_err(|message| format!("journal line 1: {}", message))?;
+    if journal_generation > generation {
+        return Err("The journal is newer than the snapshot".to_string());
+    }
+    let mut replayed = 0;
+    let mut stale = 0;
+    let mut truncated = None;
+    for (i, line) in journal_text.split_inclusive('\n').enumerate() {
+        let Some(line) = line.strip_suffix('\n') else {
+            truncated = Some(line.to_string());
+            break;
+        };
+        if i == 0 && line.starts_with("generation\t") {
+            continue;
+        }
+        if journal_generation < generation {
+            stale += 1;
+            continue;
+        }
+        apply_entry(&mut pack, line)
+            .map_err(|message| format!("journal line {}: {}", i + 1, message))?;
+        replayed += 1;
+    }
+    Ok(Recovery {
+        pack,
+        replayed,
+        stale,
+        truncated,
+    })
+}
+
+struct JournalFile {
+    path: PathBuf,
+    file: File,
+    error: Option<String>,
+    generation: u64,
+}
+
+/// An append-only log of the changes made to the packs it is attached to.
+/// Each change is written and flushed as soon as it goes through.
+#[derive(Clone)]
+pub struct Journal {
+    inner: Rc<RefCell<JournalFile>>,
+}
+
+impl Journal {
+    /// Open the journal at `path` for appending, creating it if needed. A
+    /// new journal is of generation 0.
+    pub fn open(path: &Path) -> io::Result<Journal> {
+        let generation = match fs::read_to_string(path) {
+            Ok(text) => journal_generation(&text)
+                .map_err(|message| io::Error::new(ErrorKind::InvalidData, message))?,
+            Err(error) if error.kind() == ErrorKind::NotFound => 0,
+            Err(error) => return Err(error),
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Journal {
+            inner: Rc::new(RefCell::new(JournalFile {
+                path: PathBuf::from(path),
+                file,
+                error: None,
+                generation,
+            })),
+        })
+    }
+
+    /// Start writing every change `pack` reports.
+    pub fn attach<P: PackMut + ?Sized>(&self, pack: &mut P) -> SubscriptionId {
+        let journal = self.clone();
+        pack.observers_mut()
+            .subscribe_changes(Box::new(move |change| {
+                let _ = journal.append(change);
+            }))
+    }
+
+    pub fn append(&self, change: &Change) -> io::Result<()> {
+        let mut line = encode_change(change);
+        line.push('\n');
+        let mut inner = self.inner.borrow_mut();
+        // One write per entry, so a crash can only cut the last one short.
+        let result = inner
+            .file
+            .write_all(line.as_bytes())
+            .and_then(|_| inner.file.flush());
+        if let Err(error) = &result {
+            inner.error.get_or_insert(error.to_string());
+        }
+        result
+    }
+
+    /// The first write that failed, if any. Changes after it may be
+    /// missing from the journal.
+    pub fn error(&self) -> Option<String> {
+        self.inner.borrow().error.clone()
+    }
+
+    /// Write a snapshot of `pack` to `snapshot` under the next generation,
+    /// then start the journal over under it, since everything in it is now
+    /// part of the snapshot. A crash in between leaves the old journal
+    /// behind, which `recover` skips as stale.
+    pub fn checkpoint<P: Pack + ?Sized>(&self, pack: &P, snapshot: &Path) -> io::Result<()> {
+        let mut inner = self.inner.borrow_mut();
+        let generation = inner.generation + 1;
+        write_generation(pack, snapshot, generation)?;
+        replace_file(&inner.path, &format!("generation\t{}\n", generation))?;
+        inner.file = OpenOptions::new().append(true).open(&inner.path)?;
+        inner.generation = generation;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::Item;
+    use crate::packs::{map_pack::MapPack, DensePack, SortKey, SortOrder, SortStrategy};
+
+    fn scratch(name: &str) -> PathBuf {
+        let mut path = std::env::temp_dir();
+        path.push(format!("backpack-{}-{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn recovery_replays_the_journal_over_the_snapshot() {
+        let snapshot = scratch("replay.snapshot");
+        let log = scratch("replay.journal");
+        let mut pack = MapPack::new(3, 4);
+        let lantern = Item::new("lantern", 1, 2, 'L')
+            .with_fg(Color::Rgb(255, 200, 0))
+            .with_kind("tool")
+            .with_tag("light")
+            .with_tag("tab\there")
+            .with_value(7)
+            .with_description("Line one.\nLine two.");
+        assert!(pack.add_item(lantern, Loc::new(0, 0)).is_ok());
+
+        let journal = Journal::open(&log).unwrap();
+        assert!(journal.checkpoint(&pack, &snapshot).is_ok());
+        journal.attach(&mut pack);
+        assert!(pack
+            .add_item(Item::new("coin", 1, 1, 'o'), Loc::new(2, 3))
+            .is_ok());
+        assert!(pack.transpose_item(Loc::new(0, 0)).is_ok());
+        assert!(pack.move_item(Loc::new(0, 0), Loc::new(1, 1)).is_ok());
+        assert!(pack.remove_item(Loc::new(2, 3)).is_ok());
+        // Sorting moves several items at once, some into each other's spots.
+        assert!(pack
+            .add_item(Item::new("apple", 1, 1, 'a'), Loc::new(0, 3))
+            .is_ok());
+        let key = SortKey {
+            order: SortOrder::Name,
+            ..SortKey::default()
+        };
+        assert!(pack.sort_by(key, SortStrategy::RowMajor).is_ok());
+        assert_eq!(journal.error(), None);
+
+        let recovery = recover(&snapshot, &log, MapPack::new).unwrap();
+        assert_eq!(recovery.replayed, 6);
+        assert_eq!(recovery.truncated, None);
+        assert_eq!(recovery.pack.to_string(), pack.to_string());
+        let lantern = recovery.pack.item_at(&Loc::new(1, 1)).unwrap();
+        assert_eq!(
+            lantern.item(),
+            pack.item_at(&Loc::new(1, 1)).unwrap().item()
+        );
+    }
+
+    #[test]
+    fn truncated_final_entry_is_reported_and_skipped() {
+        let snapshot = scratch("truncated.snapshot");
+        let log = scratch("truncated.journal");
+        let pack = DensePack::new(1, 3);
+        assert!(write_snapshot(&pack, &snapshot).is_ok());
+        let coin = PackedItem::new(Loc::new(0, 0), Item::new("coin", 1, 1, 'o'));
+        let text = format!("{}\nmove\t0\t0\t0\t2\t0\nremo", encode_add(&coin));
+        assert!(fs::write(&log, text).is_ok());
+
+        let recovery = recover(&snapshot, &log, DensePack::new).unwrap();
+        assert_eq!(recovery.replayed, 2);
+        assert_eq!(recovery.truncated, Some("remo".to_string()));
+        assert_eq!(recovery.pack.to_string(), "| | |o|\n");
+    }
+
+    #[test]
+    fn bad_entries_before_the_end_are_errors() {
+        let snapshot = scratch("corrupt.snapshot");
+        let log = scratch("corrupt.journal");
+        assert!(write_snapshot(&DensePack::new(1, 1), &snapshot).is_ok());
+        assert!(fs::write(&log, "remove\t0\t0\nremove\t0\t0\n").is_ok());
+
+        assert_eq!(
+            recover(&snapshot, &log, DensePack::new).map(|recovery| recovery.replayed),
+            Err("journal line 1: No item to remove".to_string())
+        );
+    }
+
+    #[test]
+    fn journal_left_by_a_checkpoint_cut_short_is_skipped() {
+        let snapshot = scratch("stale.snapshot");
+        let log = scratch("stale.journal");
+        let mut pack = DensePack::new(1, 3);
+        let journal = Journal::open(&log).unwrap();
+        assert!(journal.checkpoint(&pack, &snapshot).is_ok());
+        journal.attach(&mut pack);
+        assert!(pack
+            .add_item(Item::new("coin", 1, 1, 'o'), Loc::new(0, 0))
+            .is_ok());
+        assert!(pack.move_item("coin", Loc::new(0, 2)).is_ok());
+
+        // The next checkpoint wrote its snapshot, then crashed before
+        // starting the journal over.
+        assert!(write_generation(&pack, &snapshot, 2).is_ok());
+        let recovery = recover(&snapshot, &log, DensePack::new).unwrap();
+        assert_eq!(recovery.replayed, 0);
+        assert_eq!(recovery.stale, 2);
+        assert_eq!(recovery.pack.to_string(), "| | |o|\n");
+
+        // Reopened, the journal carries on from its own generation.
+        let journal = Journal::open(&log).unwrap();
+        assert!(journal.checkpoint(&recovery.pack, &snapshot).is_ok());
+        let recovery = recover(&snapshot, &log, DensePack::new).unwrap();
+        assert_eq!((recovery.replayed, recovery.stale), (0, 0));
+        assert_eq!(recovery.pack.to_string(), "| | |o|\n");
+    }
+
+    #[test]
+    fn journal_newer_than_its_snapshot_is_an_error() {
+        let snapshot = scratch("newer.snapshot");
+        let log = scratch("newer.journal");
+        assert!(write_snapshot(&DensePack::new(1, 1), &snapshot).is_ok());
+        assert!(fs::write(&log, "generation\t1\n").is_ok());
+
+        assert_eq!(
+            recover(&snapshot, &log, DensePack::new).map(|recovery| recovery.replayed),
+            Err("The journal is newer than the snapshot".to_string())
+        );
+    }
+}
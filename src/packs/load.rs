@@ -0,0 +1,513 @@
+use crate::items::{Loc, Orientation, PackedItem};
+use crate::packs::solve::{self, Budget};
+use crate::packs::{space, Pack, PackMut};
+
+use std::time::Duration;
+
+/// Up to this many candidate items the optimizer searches exactly. Beyond
+/// it, the greedy heuristic described on `plan_load` is used instead.
+pub const EXACT_ITEM_LIMIT: usize = 12;
+
+/// How long the exact search may run unless told otherwise.
+pub const DEFAULT_LOAD_BUDGET: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct LoadOptions {
+    /// The most the destination pack may weigh once loaded, counting what
+    /// it already holds.
+    pub weight_limit: Option<u32>,
+    pub allow_transpose: bool,
+    /// How long the exact search may run before settling for the best load
+    /// found so far. Without a budget it always runs to the end.
+    pub time_budget: Option<Duration>,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        LoadOptions {
+            weight_limit: None,
+            allow_transpose: true,
+            time_budget: Some(DEFAULT_LOAD_BUDGET),
+        }
+    }
+}
+
+/// Where one item goes: from the cell `src` of one pack to the anchor
+/// `dst` of another, transposed on the way if asked.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Placement {
+    pub src: Loc,
+    pub dst: Loc,
+    pub transpose: bool,
+}
+
+/// The items chosen by the optimizer and what they add up to.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Load {
+    pub placements: Vec<Placement>,
+    pub value: u32,
+    pub weight: u32,
+    /// Whether the load is known to be optimal.
+    pub exact: bool,
+}
+
+struct Candidate {
+    src: Loc,
+    rows: u32,
+    cols: u32,
+    value: u32,
+    weight: u32,
+}
+
+impl Candidate {
+    fn area(&self) -> u32 {
+        self.rows * self.cols
+    }
+
+    /// The footprints the candidate may take, with whether each one is
+    /// transposed.
+    fn shapes(&self, allow_transpose: bool) -> Vec<(u32, u32, bool)> {
+        let mut shapes = vec![(self.rows, self.cols, false)];
+        if allow_transpose && self.rows != self.cols {
+            shapes.push((self.cols, self.rows, true));
+        }
+        shapes
+    }
+}
+
+fn fits(occupied: &[Vec<bool>], loc: Loc, rows: u32, cols: u32) -> bool {
+    let pack_rows = occupied.len() as u32;
+    let pack_cols = occupied.first().map_or(0, |row| row.len()) as u32;
+    loc.row() + rows <= pack_rows
+        && loc.col() + cols <= pack_cols
+        && (loc.row()..loc.row() + rows)
+            .all(|r| (loc.col()..loc.col() + cols).all(|c| !occupied[r as usize][c as usize]))
+}
+
+fn fill(occupied: &mut [Vec<bool>], loc: Loc, rows: u32, cols: u32, value: bool) {
+    for r in loc.row()..loc.row() + rows {
+        for c in loc.col()..loc.col() + cols {
+            occupied[r as usize][c as usize] = value;
+        }
+    }
+}
+
+fn anchors(occupied: &[Vec<bool>]) -> impl Iterator<Item = Loc> {
+    let rows = occupied.len() as u32;
+    let cols = occupied.first().map_or(0, |row| row.len()) as u32;
+    (0..rows).flat_map(move |r| (0..cols).map(move |c| Loc::new(r, c)))
+}
+
+/// Try the subsets of `candidates` that would beat `best`, most valuable
+/// first, and return the first one that can be arranged. `best` itself
+/// comes back if none can, or if the budget runs out first.
+fn exact(
+    occupied: &[Vec<bool>],
+    candidates: &[Candidate],
+    capacity: Option<u32>,
+    allow_transpose: bool,
+    best: Load,
+    budget: &mut Budget,
+) -> Load {
+    let free = occupied.iter().flatten().filter(|&&cell| !cell).count() as u32;
+
+    // Every subset that could fit by weight and area and would be better
+    // than `best`, best first.
+    let mut subsets: Vec<(u32, u32, u32)> = (1u32..1 << candidates.len())
+        .map(|mask| {
+            let chosen = candidates
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| mask & (1 << i) != 0);
+            let (value, weight) = chosen.fold((0, 0), |(value, weight), (_, candidate)| {
+                (value + candidate.value, weight + candidate.weight)
+            });
+            (value, weight, mask)
+        })
+        .filter(|&(value, weight, mask)| {
+            let area: u32 = candidates
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| mask & (1 << i) != 0)
+                .map(|(_, candidate)| candidate.area())
+                .sum();
+            (value, std::cmp::Reverse(weight)) > (best.value, std::cmp::Reverse(best.weight))
+                && capacity.is_none_or(|capacity| weight <= capacity)
+                && area <= free
+        })
+        .collect();
+    subsets.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+    for (value, weight, mask) in subsets {
+        let set: Vec<&Candidate> = candidates
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| mask & (1 << i) != 0)
+            .map(|(_, candidate)| candidate)
+            .collect();
+        let sizes: Vec<(u32, u32)> = set
+            .iter()
+            .map(|candidate| (candidate.rows, candidate.cols))
+            .collect();
+        if let Some(fits) = solve::arrange(occupied.to_vec(), &sizes, allow_transpose, budget) {
+            return Load {
+                placements: set
+                    .iter()
+                    .zip(fits)
+                    .map(|(candidate, fit)| Placement {
+                        src: candidate.src,
+                        dst: fit.loc,
+                        transpose: fit.orientation == Orientation::Transposed,
+                    })
+                    .collect(),
+                value,
+                weight,
+                exact: true,
+            };
+        }
+        if budget.is_spent() {
+            return best;
+        }
+    }
+    Load {
+        exact: true,
+        ..best
+    }
+}
+
+fn greedy(
+    occupied: &[Vec<bool>],
+    candidates: &[Candidate],
+    capacity: Option<u32>,
+    allow_transpose: bool,
+) -> Load {
+    let mut occupied = occupied.to_vec();
+    let mut order: Vec<&Candidate> = candidates.iter().collect();
+    // Best value per cell first; the more valuable of equally dense items
+    // first.
+    order.sort_by(|a, b| {
+        (b.value as u64 * a.area() as u64)
+            .cmp(&(a.value as u64 * b.area() as u64))
+            .then(b.value.cmp(&a.value))
+    });
+
+    let mut load = Load::default();
+    for candidate in order {
+        if capacity.is_some_and(|capacity| load.weight + candidate.weight > capacity) {
+            continue;
+        }
+        let spot =
+            candidate
+                .shapes(allow_transpose)
+                .into_iter()
+                .find_map(|(rows, cols, transpose)| {
+                    anchors(&occupied)
+                        .find(|&loc| fits(&occupied, loc, rows, cols))
+                        .map(|loc| (loc, rows, cols, transpose))
+                });
+        if let Some((dst, rows, cols, transpose)) = spot {
+            fill(&mut occupied, dst, rows, cols, true);
+            load.placements.push(Placement {
+                src: candidate.src,
+                dst,
+                transpose,
+            });
+            load.value += candidate.value;
+            load.weight += candidate.weight;
+        }
+    }
+    load
+}
+
+/// Choose the items of `store` worth the most that fit together in the
+/// free space of `pack`, without carrying them over.
+///
+/// Items are first taken greedily by value per cell, each at the first
+/// spot in reading order where it fits upright or else transposed, skipping
+/// any that would break the weight limit. With at most `EXACT_ITEM_LIMIT`
+/// valuable candidates, every subset that beats it, being worth more or as
+/// much for less weight, is then tried from the most valuable down, and
+/// the first one `solve::arrange` can lay out is returned, so the load is
+/// optimal. If the time budget runs out first, the greedy load is returned
+/// and is not marked exact.
+pub fn plan_load<S, D>(store: &S, pack: &D, options: LoadOptions) -> Load
+where
+    S: Pack + ?Sized,
+    D: Pack + ?Sized,
+{
+    let candidates: Vec<Candidate> = store
+        .iter()
+        .filter(|packed_item| packed_item.value() > 0)
+        .map(|packed_item| Candidate {
+            src: packed_item.loc(),
+            rows: packed_item.rows(),
+            cols: packed_item.cols(),
+            value: packed_item.value(),
+            weight: packed_item.weight(),
+        })
+        .collect();
+    let capacity = options
+        .weight_limit
+        .map(|limit| limit.saturating_sub(pack.total_weight()));
+    let occupied = space::blocked(pack);
+
+    let greedy = greedy(&occupied, &candidates, capacity, options.allow_transpose);
+    if candidates.len() > EXACT_ITEM_LIMIT {
+        return greedy;
+    }
+    let mut budget = Budget::new(options.time_budget);
+    exact(
+        &occupied,
+        &candidates,
+        capacity,
+        options.allow_transpose,
+        greedy,
+        &mut budget,
+    )
+}
+
+/// Move items from one pack to another as a single operation. If any item
+/// cannot be taken out or put in, every item is put back where it was.
+pub fn transfer<S, D>(from: &mut S, to: &mut D, placements: &[Placement]) -> Result<(), String>
+where
+    S: PackMut + ?Sized,
+    D: PackMut + ?Sized,
+{
+    let from_mark = from.history().recorded();
+    let to_mark = to.history().recorded();
+    let from_held = from.observers_mut().hold();
+    let to_held = to.observers_mut().hold();
+    let mut taken: Vec<PackedItem> = Vec::new();
+    for placement in placements {
+        match from.remove_item_at(placement.src) {
+            Ok(packed_item) => taken.push(packed_item),
+            Err(message) => {
+                for packed_item in taken {
+                    // Its spot was just vacated, so this cannot fail.
+                    let _ = from.restore(packed_item);
+                }
+                from.history_mut().forget_since(from_mark);
+                from.observers_mut().discard(from_held);
+                to.observers_mut().discard(to_held);
+                return Err(format!(
+                    "Could not take the item at {}: {}",
+                    placement.src, message
+                ));
+            }
+        }
+    }
+
+    let mut added: Vec<Loc> = Vec::new();
+    for (packed_item, placement) in taken.iter().zip(placements) {
+        let mut item = packed_item.clone().unpack();
+        if placement.transpose {
+            item.transpose();
+        }
+        match to.add_item(item, placement.dst) {
+            Ok(loc) => added.push(loc),
+            Err(message) => {
+                for loc in added {
+                    to.evict(loc);
+                }
+                for packed_item in taken {
+                    let _ = from.restore(packed_item);
+                }
+                from.history_mut().forget_since(from_mark);
+                to.history_mut().forget_since(to_mark);
+                from.observers_mut().discard(from_held);
+                to.observers_mut().discard(to_held);
+                return Err(message);
+            }
+        }
+    }
+    from.observers_mut().release();
+    to.observers_mut().release();
+    Ok(())
+}
+
+/// Carry the most valuable load that fits from `store` into `pack`, as one
+/// atomic transfer.
+pub fn load_best<S, D>(store: &mut S, pack: &mut D, options: LoadOptions) -> Result<Load, String>
+where
+    S: PackMut + ?Sized,
+    D: PackMut + ?Sized,
+{
+    let load = plan_load(store, pack, options);
+    transfer(store, pack, &load.placements)?;
+    Ok(load)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::behavior::{Behavior, ItemBehavior};
+    use crate::items::{Item, Kind};
+    use crate::packs::{map_pack::MapPack, DensePack};
+
+    fn store_with(items: Vec<Item>) -> DensePack {
+        let mut store = DensePack::new(items.len() as u32 * 3, 3);
+        for (i, item) in items.into_iter().enumerate() {
+            assert!(store.add_item(item, Loc::new(i as u32 * 3, 0)).is_ok());
+        }
+        store
+    }
+
+    #[test]
+    fn exact_search_beats_greedy_choice() {
+        // Greedy by density takes the 1x1 gem first, after which the 2x2
+        // crown no longer fits. The crown alone is worth more.
+        let store = store_with(vec![
+            Item::new("gem", 1, 1, 'g').with_value(10),
+            Item::new("crown", 2, 2, 'c').with_value(30),
+        ]);
+        let pack = MapPack::new(2, 2);
+
+        let load = plan_load(&store, &pack, LoadOptions::default());
+        assert!(load.exact);
+        assert_eq!(load.value, 30);
+        assert_eq!(load.placements.len(), 1);
+    }
+
+    #[test]
+    fn transposition_and_weight_limit_are_respected() {
+        let store = store_with(vec![
+            Item::new("spear", 3, 1, '/').with_value(20).with_weight(4),
+            Item::new("anvil", 1, 1, 'A').with_value(25).with_weight(9),
+        ]);
+        let pack = DensePack::new(1, 3);
+
+        let options = LoadOptions {
+            weight_limit: Some(5),
+            ..LoadOptions::default()
+        };
+        let load = plan_load(&store, &pack, options);
+        assert_eq!(load.value, 20);
+        assert_eq!(
+            load.placements,
+            vec![Placement {
+                src: Loc::new(0, 0),
+                dst: Loc::new(0, 0),
+                transpose: true,
+            }]
+        );
+
+        let options = LoadOptions {
+            weight_limit: Some(5),
+            allow_transpose: false,
+            ..LoadOptions::default()
+        };
+        assert_eq!(plan_load(&store, &pack, options).value, 0);
+    }
+
+    #[test]
+    fn load_best_moves_chosen_items_across() {
+        let mut store = store_with(vec![
+            Item::new("gem", 1, 1, 'g').with_value(10),
+            Item::new("rock", 1, 1, '*'),
+        ]);
+        let mut pack = DensePack::new(1, 1);
+
+        let load = load_best(&mut store, &mut pack, LoadOptions::default());
+        assert_eq!(load.map(|load| load.value), Ok(10));
+        assert_eq!(pack.grab_item(&Loc::new(0, 0)).unwrap().name(), "gem");
+        assert!(store.grab_item(&Loc::new(0, 0)).is_none());
+        assert!(store.grab_item(&Loc::new(3, 0)).is_some());
+    }
+
+    #[test]
+    fn failed_transfer_puts_everything_back() {
+        let mut store = store_with(vec![
+            Item::new("gem", 1, 1, 'g').with_value(10),
+            Item::new("coin", 1, 1, 'o').with_value(5),
+        ]);
+        let mut pack = DensePack::new(1, 2);
+
+        // Both items aimed at the same spot: the second add fails.
+        let placements = [
+            Placement {
+                src: Loc::new(0, 0),
+                dst: Loc::new(0, 1),
+                transpose: false,
+            },
+            Placement {
+                src: Loc::new(3, 0),
+                dst: Loc::new(0, 1),
+                transpose: false,
+            },
+        ];
+        assert!(transfer(&mut store, &mut pack, &placements).is_err());
+        assert!(pack.grab_item(&Loc::new(0, 1)).is_none());
+        assert_eq!(store.grab_item(&Loc::new(0, 0)).unwrap().name(), "gem");
+        assert_eq!(store.grab_item(&Loc::new(3, 0)).unwrap().name(), "coin");
+    }
+
+    struct Cursed;
+
+    impl ItemBehavior for Cursed {
+        fn on_remove(&self, _item: &PackedItem, _pack: &dyn Pack) -> Result<(), String> {
+            Err("It will not leave.".to_string())
+        }
+    }
+
+    #[test]
+    fn vetoed_removal_aborts_the_whole_load() {
+        let mut store = store_with(vec![
+            Item::new("gem", 1, 1, 'g').with_value(10),
+            Item::new("idol", 1, 1, 'i')
+                .with_value(5)
+                .with_kind(Kind::new("relic").with_behavior(Behavior::new(Cursed))),
+        ]);
+        let mut pack = MapPack::new(1, 2);
+
+        assert!(load_best(&mut store, &mut pack, LoadOptions::default()).is_err());
+        assert_eq!(pack.iter().count(), 0);
+        assert_eq!(store.grab_item(&Loc::new(0, 0)).unwrap().name(), "gem");
+        assert_eq!(store.grab_item(&Loc::new(3, 0)).unwrap().name(), "idol");
+    }
+
+    #[test]
+    fn many_candidates_fall_back_to_greedy() {
+        let items = (0..EXACT_ITEM_LIMIT as u32 + 1)
+            .map(|i| Item::new(&format!("coin{}", i), 1, 1, 'o').with_value(i + 1))
+            .collect();
+        let store = store_with(items);
+        let pack = DensePack::new(1, 2);
+
+        let load = plan_load(&store, &pack, LoadOptions::default());
+        assert!(!load.exact);
+        assert_eq!(load.value, 13 + 12);
+    }
+
+    #[test]
+    fn hopeless_search_gives_up_with_the_best_load_so_far() {
+        // The free cells form 2x2 pockets, so the valuable 1x3 rod fits
+        // nowhere. The search only finds out after trying every way of
+        // laying out the small items around it, which takes far too long.
+        let mut items = vec![Item::new("rod", 1, 3, '-').with_value(100)];
+        for i in 0..EXACT_ITEM_LIMIT as u32 - 1 {
+            let (rows, cols) = [(2, 2), (1, 2), (1, 1)][i as usize % 3];
+            items.push(Item::new(&format!("bit{}", i), rows, cols, 'b').with_value(1));
+        }
+        let mut store = store_with(items);
+        let mut pack = MapPack::new(8, 8);
+        for r in 0..8 {
+            for c in 0..8 {
+                if r % 3 == 2 || c % 3 == 2 {
+                    let wall = Item::new("wall", 1, 1, '#');
+                    assert!(pack.add_item(wall, Loc::new(r, c)).is_ok());
+                }
+            }
+        }
+
+        let options = LoadOptions {
+            time_budget: Some(Duration::from_millis(50)),
+            ..LoadOptions::default()
+        };
+        let started = std::time::Instant::now();
+        let load = load_best(&mut store, &mut pack, options).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(!load.exact);
+        assert_eq!(load.value, EXACT_ITEM_LIMIT as u32 - 1);
+        assert_eq!(pack.iter().count(), 28 + EXACT_ITEM_LIMIT - 1);
+    }
+}
@@ -1,12 +1,22 @@
-use crate::items::{Item, Loc, PackedItem};
+use crate::behavior::{Motion, UseOutcome};
+use crate::items::{Item, Loc, PackedItem, Rect};
+use crate::packs::{
+    invariants, slide, Change, History, InvariantReport, Items, MoveMode, NamePolicy, Observers,
+    Pack, PackMut, Rects, RenderMode, Reservation, Reservations, Violation,
+};
 
 use std::collections::BTreeMap;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct MapPack {
     rows: u32,
     cols: u32,
     items: BTreeMap<Loc, PackedItem>,
+    name_policy: NamePolicy,
+    move_mode: MoveMode,
+    history: History,
+    observers: Observers,
+    reservations: Reservations,
 }
 
 impl MapPack {
@@ -23,125 +33,374 @@ impl MapPack {
             rows,
             cols,
             items: BTreeMap::new(),
+            name_policy: NamePolicy::default(),
+            move_mode: MoveMode::default(),
+            history: History::default(),
+            observers: Observers::default(),
+            reservations: Reservations::default(),
         }
     }
 
+    pub fn with_name_policy(mut self, name_policy: NamePolicy) -> Self {
+        self.name_policy = name_policy;
+        self
+    }
+
+    pub fn name_policy(&self) -> NamePolicy {
+        self.name_policy
+    }
+
+    pub fn with_move_mode(mut self, move_mode: MoveMode) -> Self {
+        self.move_mode = move_mode;
+        self
+    }
+
+    pub fn move_mode(&self) -> MoveMode {
+        self.move_mode
+    }
+
+    /// Remember only the last `limit` changes for undo.
+    pub fn with_history_limit(mut self, limit: usize) -> Self {
+        self.history = History::new(limit);
+        self
+    }
+
     fn grab_item(&self, loc: &Loc) -> Option<&PackedItem> {
-        self.items.get(&loc)
+        self.items.get(loc)
     }
 
     fn find_item(&self, loc: &Loc) -> Option<&PackedItem> {
-        for packed_item in self.items.values() {
-            if packed_item.contains(loc) {
-                return Some(packed_item);
+        self.items
+            .values()
+            .find(|packed_item| packed_item.contains(loc))
+    }
+
+    fn name_is_taken(&self, name: &str) -> bool {
+        self.items
+            .values()
+            .any(|packed_item| packed_item.name() == name)
+    }
+
+    /// Verify that every item is in bounds and keyed by its anchor, and
+    /// that no two items, or an item and a reservation, overlap.
+    pub fn check_invariants(&self) -> InvariantReport {
+        let mut report = invariants::check(self);
+        for (key, packed_item) in &self.items {
+            if *key != packed_item.loc() {
+                report.violations.push(Violation::MisplacedKey {
+                    key: *key,
+                    item: packed_item.clone(),
+                });
             }
         }
-        None
+        report
     }
 
-    fn item_placement_exceeds_bounds(&self, item: &PackedItem) -> bool {
-        return item.row() >= self.rows
-            || item.col() >= self.cols
-            || item.row() + item.rows() > self.rows
-            || item.col() + item.cols() > self.cols;
+    /// Tell the listeners about a change that went through, and remember it
+    /// for undo.
+    fn commit(&mut self, change: Change) {
+        invariants::assert_ok(|| self.check_invariants());
+        self.observers.notify(&change);
+        self.history.record(change);
     }
 
-    fn item_placement_intersects_contents(&self, item: &PackedItem) -> bool {
-        for packed_item in self.items.values() {
-            if item == packed_item {
-                continue;
-            }
-            if item.intersects(&packed_item) {
-                return true;
+    /// Run the on_move hook of the item anchored at `src`, then apply the
+    /// motion unless the resulting placement would be invalid.
+    fn apply_motion(&mut self, src: Loc, motion: Motion) -> Result<Loc, String> {
+        let mut motion = motion;
+        let item = self.items.get(&src).ok_or("Invalid loc")?;
+        if let Some(behavior) = item.behavior() {
+            behavior.on_move(item, &mut motion, self)?;
+        }
+        if !self.motion_is_valid(item, motion) {
+            if motion.transpose {
+                return Err("Invalid transposition.".to_string());
             }
+            return Err("Invalid move".to_string());
         }
-        false
-    }
 
-    fn item_placement_is_invalid(&self, item: &PackedItem) -> bool {
-        return self.item_placement_exceeds_bounds(item)
-            || self.item_placement_intersects_contents(item);
+        let before = self.items.remove(&src).unwrap();
+        let mut item = before.clone();
+        item.move_to(motion.dst);
+        if motion.transpose {
+            item.transpose();
+        }
+        self.items.insert(motion.dst, item.clone());
+        self.commit(Change::Moved(vec![(before, item)]));
+        Ok(motion.dst)
     }
 
     pub fn add_item(&mut self, item: Item, loc: Loc) -> Result<Loc, String> {
+        let mut item = item;
+        let mut loc = loc;
+        if let Some(behavior) = item.behavior().cloned() {
+            behavior.on_add(&mut item, &mut loc, self)?;
+        }
+        let name = self
+            .name_policy
+            .resolve(item.name(), |name| self.name_is_taken(name))?;
+        item.rename(&name);
         let tentative = PackedItem::new(loc, item);
 
         // Invalid loc for this Pack.
-        if self.item_placement_exceeds_bounds(&tentative) {
+        if !self.placement_in_bounds(&tentative) {
             return Err("Item placement is invalid".to_string());
         }
 
-        // Item overlaps other items.
-        if self.item_placement_intersects_contents(&tentative) {
+        // Item overlaps other items or reserved cells.
+        if !self.placement_is_valid(&tentative) {
             return Err("Item intersects existing Pack contents.".to_string());
         }
 
         let result_loc = tentative.loc();
-        self.items.insert(result_loc.clone(), tentative);
+        self.items.insert(result_loc, tentative.clone());
+        self.commit(Change::Added(tentative));
         Ok(result_loc)
     }
 
-    pub fn remove_item(&mut self, loc: Loc) -> Option<PackedItem> {
-        self.items.remove(&loc)
+    /// Remove the item anchored at `loc`. Fails when there is no such item
+    /// or its behavior refuses removal.
+    pub fn remove_item(&mut self, loc: Loc) -> Result<PackedItem, String> {
+        let item = self.items.get(&loc).ok_or("Invalid loc")?;
+        if let Some(behavior) = item.behavior() {
+            behavior.on_remove(item, self)?;
+        }
+        let packed_item = self.items.remove(&loc).ok_or("Invalid loc")?;
+        self.commit(Change::Removed(packed_item.clone()));
+        Ok(packed_item)
     }
 
     pub fn transpose_item(&mut self, loc: Loc) -> Result<Loc, String> {
-        // Do the tranposition.
-        match self.items.get_mut(&loc) {
-            Some(item) => {
-                item.transpose();
+        self.apply_motion(
+            loc,
+            Motion {
+                dst: loc,
+                transpose: true,
+            },
+        )
+    }
+
+    /// Move the item anchored at `src` so it is anchored at `dst`, straight
+    /// there or cell by cell depending on the pack's move mode.
+    pub fn move_item(&mut self, src: Loc, dst: Loc) -> Result<Loc, String> {
+        if self.move_mode == MoveMode::Sliding {
+            if !self.items.contains_key(&src) {
+                return Err("Invalid loc".to_string());
             }
-            None => {
-                return Err("Invalid location".to_string());
+            let path = match self.path_to(src, dst) {
+                Some(path) => path,
+                None => return Err(format!("The item cannot slide to {}", dst)),
+            };
+            return slide::follow(self, src, &path, MapPack::jump_item);
+        }
+        self.jump_item(src, dst)
+    }
+
+    fn jump_item(&mut self, src: Loc, dst: Loc) -> Result<Loc, String> {
+        self.apply_motion(
+            src,
+            Motion {
+                dst,
+                transpose: false,
+            },
+        )
+    }
+
+    /// Use the item covering `loc`. An item its behavior consumes leaves
+    /// the pack.
+    pub fn use_item_at(&mut self, loc: Loc) -> Result<UseOutcome, String> {
+        let item = self.find_item(&loc).ok_or("Invalid loc")?;
+        let anchor = item.loc();
+        let mut outcome = UseOutcome::Keep;
+        if let Some(behavior) = item.behavior() {
+            outcome = behavior.on_use(item, self)?;
+        }
+        if outcome == UseOutcome::Consume {
+            if let Some(packed_item) = self.items.remove(&anchor) {
+                self.commit(Change::Removed(packed_item));
             }
         }
+        Ok(outcome)
+    }
+}
+
+impl Pack for MapPack {
+    fn rows(&self) -> u32 {
+        self.rows
+    }
+
+    fn cols(&self) -> u32 {
+        self.cols
+    }
+
+    fn packed_items(&self) -> Items<'_> {
+        Box::new(self.items.values())
+    }
+
+    fn reserved(&self) -> Rects<'_> {
+        Box::new(self.reservations.active().map(Reservation::rect))
+    }
+
+    fn item_at(&self, loc: &Loc) -> Option<&PackedItem> {
+        self.find_item(loc)
+    }
+
+    /// Items are keyed by anchor, which already is reading order.
+    fn iter(&self) -> Items<'_> {
+        Box::new(self.items.values())
+    }
 
-        // Undo the transposition if placement is invalid.
-        if self.item_placement_is_invalid(&self.items[&loc]) {
-            self.items.get_mut(&loc).unwrap().transpose();
-            return Err("Invalid transposition.".to_string());
+    /// Nothing anchored at or below the bottom edge of `rect` can reach it,
+    /// so only the anchors above that edge are scanned.
+    fn items_in(&self, rect: Rect) -> Items<'_> {
+        let end = Loc::new(rect.row() + rect.rows(), 0);
+        Box::new(
+            self.items
+                .range(..end)
+                .map(|(_, packed_item)| packed_item)
+                .filter(move |packed_item| packed_item.rect().intersects(&rect)),
+        )
+    }
+}
+
+impl PackMut for MapPack {
+    fn restore(&mut self, item: PackedItem) -> Result<Loc, String> {
+        if !self.placement_is_valid(&item) {
+            return Err("Item placement is invalid".to_string());
         }
+        let loc = item.loc();
+        self.items.insert(loc, item);
+        invariants::assert_ok(|| self.check_invariants());
         Ok(loc)
     }
 
-    pub fn move_item(&mut self, src: Loc, dst: Loc) -> Result<Loc, String> {
-        // Do the move.
-        match self.items.get_mut(&src) {
-            Some(item) => {
-                item.move_to(dst.clone());
+    fn evict(&mut self, loc: Loc) -> Option<PackedItem> {
+        let anchor = self.find_item(&loc)?.loc();
+        self.items.remove(&anchor)
+    }
+
+    fn history(&self) -> &History {
+        &self.history
+    }
+
+    fn history_mut(&mut self) -> &mut History {
+        &mut self.history
+    }
+
+    fn observers_mut(&mut self) -> &mut Observers {
+        &mut self.observers
+    }
+
+    fn reservations(&self) -> &Reservations {
+        &self.reservations
+    }
+
+    fn reservations_mut(&mut self) -> &mut Reservations {
+        &mut self.reservations
+    }
+
+    fn add_item(&mut self, item: Item, loc: Loc) -> Result<Loc, String> {
+        MapPack::add_item(self, item, loc)
+    }
+
+    fn remove_item_at(&mut self, loc: Loc) -> Result<PackedItem, String> {
+        let anchor = self.find_item(&loc).ok_or("Invalid loc")?.loc();
+        self.remove_item(anchor)
+    }
+
+    fn move_item_at(&mut self, src: Loc, dst: Loc) -> Result<Loc, String> {
+        match self.find_item(&src) {
+            Some(packed_item) => self.move_item(packed_item.loc(), dst),
+            None => Err("Invalid loc".to_string()),
+        }
+    }
+
+    fn transpose_item_at(&mut self, loc: Loc) -> Result<Loc, String> {
+        match self.find_item(&loc) {
+            Some(packed_item) => self.transpose_item(packed_item.loc()),
+            None => Err("Invalid loc".to_string()),
+        }
+    }
+
+    fn apply_motions(&mut self, motions: &[(Loc, Motion)]) -> Result<(), String> {
+        // Resolve every motion against the pack as it is now.
+        let mut planned: Vec<(Loc, Motion)> = Vec::new();
+        for (src, motion) in motions {
+            let packed_item = match self.find_item(src) {
+                Some(packed_item) => packed_item,
+                None => return Err("Invalid loc".to_string()),
+            };
+            let anchor = packed_item.loc();
+            if planned
+                .iter()
+                .any(|(planned_anchor, _)| *planned_anchor == anchor)
+            {
+                return Err("More than one motion for the same item".to_string());
             }
-            None => {
-                return Err("Invalid loc".to_string());
+            let mut motion = *motion;
+            if let Some(behavior) = packed_item.behavior() {
+                behavior.on_move(packed_item, &mut motion, self)?;
             }
+            planned.push((anchor, motion));
         }
 
-        // Undo the move if placement is invalid.
-        if self.item_placement_is_invalid(&self.items[&src]) {
-            self.items.get_mut(&src).unwrap().move_to(src);
-            return Err("Invalid move".to_string());
+        // Check every new placement against the items staying put and
+        // against each other, then do the motions.
+        let anchors: Vec<Loc> = planned.iter().map(|(anchor, _)| *anchor).collect();
+        let originals: Vec<PackedItem> = anchors
+            .iter()
+            .map(|anchor| self.items[anchor].clone())
+            .collect();
+        let mut moved: Vec<PackedItem> = Vec::new();
+        for (original, (_, motion)) in originals.iter().zip(&planned) {
+            let mut packed_item = original.clone();
+            packed_item.move_to(motion.dst);
+            if motion.transpose {
+                packed_item.transpose();
+            }
+            if !self.placement_is_valid_moving(&packed_item, &anchors)
+                || moved.iter().any(|other| other.intersects(&packed_item))
+            {
+                return Err("Invalid rearrangement".to_string());
+            }
+            moved.push(packed_item);
         }
-
-        let item = self.items.remove(&src).unwrap();
-        self.items.insert(dst.clone(), item);
-        Ok(dst)
+        for anchor in &anchors {
+            self.items.remove(anchor);
+        }
+        for packed_item in &moved {
+            self.items.insert(packed_item.loc(), packed_item.clone());
+        }
+        self.commit(Change::Moved(originals.into_iter().zip(moved).collect()));
+        Ok(())
     }
 }
 
 use std::fmt;
+use std::hash::{Hash, Hasher};
 impl fmt::Display for MapPack {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        const SEP: char = '|';
-        for r in 0..self.rows {
-            for c in 0..self.cols {
-                let mut next_symbol: char = ' ';
-                if let Some(packed_item) = self.find_item(&Loc::new(r, c)) {
-                    next_symbol = packed_item.symbol();
-                }
-                write!(f, "{}{}", SEP, next_symbol)?;
-            }
-            write!(f, "{}\n", SEP)?;
+        self.render(RenderMode::Plain).fmt(f)
+    }
+}
+
+/// Two packs are equal when they are the same size and hold equal items at
+/// the same places, whatever order the items went in. Settings, history,
+/// listeners and reservations are not compared.
+impl PartialEq for MapPack {
+    fn eq(&self, other: &Self) -> bool {
+        (self.rows, self.cols) == (other.rows, other.cols) && self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for MapPack {}
+
+impl Hash for MapPack {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.rows, self.cols).hash(state);
+        for packed_item in self.iter() {
+            packed_item.hash(state);
         }
-        Ok(())
     }
 }
 
@@ -152,7 +411,7 @@ mod tests {
     #[test]
     fn add_1x1_item_to_1x1_pack() {
         let mut pack = MapPack::new(1, 1);
-        let pebble = Item::new(1, 1, '.');
+        let pebble = Item::new("pebble", 1, 1, '.');
 
         let result = pack.add_item(pebble, Loc::new(0, 0));
         assert!(result.is_ok());
@@ -161,7 +420,7 @@ mod tests {
     #[test]
     fn add_item_with_out_of_bounds_coordinates_is_an_error() {
         let mut pack = MapPack::new(1, 1);
-        let pebble = Item::new(1, 1, '.');
+        let pebble = Item::new("pebble", 1, 1, '.');
 
         let result = pack.add_item(pebble, Loc::new(1, 0));
         assert!(result.is_err());
@@ -170,8 +429,8 @@ mod tests {
     #[test]
     fn add_item_intersecting_existing_item_is_an_error() {
         let mut pack = MapPack::new(3, 3);
-        let stick = Item::new(1, 2, '*');
-        let stone = Item::new(2, 2, '@');
+        let stick = Item::new("stick", 1, 2, '*');
+        let stone = Item::new("stone", 2, 2, '@');
 
         let result = pack.add_item(stick, Loc::new(0, 0));
         assert!(result.is_ok());
@@ -183,7 +442,7 @@ mod tests {
     #[test]
     fn add_item_that_exceeds_pack_size_is_an_error() {
         let mut pack = MapPack::new(1, 1);
-        let cat = Item::new(3, 2, 'c');
+        let cat = Item::new("cat", 3, 2, 'c');
 
         let result = pack.add_item(cat, Loc::new(0, 0));
         assert!(result.is_err());
@@ -192,7 +451,7 @@ mod tests {
     #[test]
     fn tranpose_item_valid_transposition_succeeds() {
         let mut pack = MapPack::new(3, 3);
-        let stick = Item::new(1, 3, '*');
+        let stick = Item::new("stick", 1, 3, '*');
 
         let result = pack.add_item(stick, Loc::new(0, 0));
         assert!(result.is_ok());
@@ -204,8 +463,8 @@ mod tests {
     #[test]
     fn transpose_item_causing_intersection_is_an_error() {
         let mut pack = MapPack::new(3, 3);
-        let stick = Item::new(1, 3, '*');
-        let stone = Item::new(1, 1, '@');
+        let stick = Item::new("stick", 1, 3, '*');
+        let stone = Item::new("stone", 1, 1, '@');
 
         let add_stick = pack.add_item(stick, Loc::new(0, 0));
         assert!(add_stick.is_ok());
@@ -218,28 +477,28 @@ mod tests {
     }
 
     #[test]
-    fn remove_item_from_unoccupied_space_returns_none() {
+    fn remove_item_from_unoccupied_space_is_an_error() {
         let mut pack = MapPack::new(3, 3);
         let removed = pack.remove_item(Loc::new(0, 0));
-        assert!(removed.is_none());
+        assert_eq!(removed.err(), Some("Invalid loc".to_string()));
     }
 
     #[test]
-    fn remove_item_at_occupied_location_returns_some_packed_item() {
+    fn remove_item_at_occupied_location_returns_the_packed_item() {
         let mut pack = MapPack::new(3, 3);
-        let stone = Item::new(1, 1, '*');
+        let stone = Item::new("stone", 1, 1, '*');
 
         let result = pack.add_item(stone.clone(), Loc::new(0, 0));
         assert!(result.is_ok());
 
         let removed = pack.remove_item(result.unwrap());
-        assert!(matches!(removed, stone));
+        assert_eq!(removed.map(PackedItem::unpack), Ok(stone));
     }
 
     #[test]
     fn move_item_to_out_of_bounds_location_is_an_error() {
         let mut pack = MapPack::new(1, 1);
-        let stone = Item::new(1, 1, '*');
+        let stone = Item::new("stone", 1, 1, '*');
 
         let result = pack.add_item(stone, Loc::new(0, 0));
         assert!(result.is_ok());
@@ -251,9 +510,69 @@ mod tests {
     #[test]
     fn move_item_is_correct() {
         let mut pack = MapPack::new(1, 2);
-        let a = Item::new(1, 1, '^');
+        let a = Item::new("a", 1, 1, '^');
 
         let result = pack.add_item(a, Loc::new(0, 0));
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn add_item_with_duplicate_name_is_an_error_when_rejecting_duplicates() {
+        let mut pack = MapPack::new(1, 2).with_name_policy(NamePolicy::RejectDuplicates);
+
+        let result = pack.add_item(Item::new("stone", 1, 1, '*'), Loc::new(0, 0));
+        assert!(result.is_ok());
+
+        let result = pack.add_item(Item::new("stone", 1, 1, '*'), Loc::new(0, 1));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn add_item_with_duplicate_name_is_suffixed_when_auto_suffixing() {
+        let mut pack = MapPack::new(1, 2).with_name_policy(NamePolicy::AutoSuffix);
+
+        let result = pack.add_item(Item::new("torch", 1, 1, '&'), Loc::new(0, 0));
+        assert!(result.is_ok());
+        let result = pack.add_item(Item::new("torch", 1, 1, '&'), Loc::new(0, 1));
+        assert!(result.is_ok());
+
+        assert_eq!(pack.grab_item(&Loc::new(0, 1)).unwrap().name(), "torch#2");
+    }
+
+    #[test]
+    fn check_invariants_reports_items_under_the_wrong_key() {
+        let mut pack = MapPack::new(1, 3);
+        let result = pack.add_item(Item::new("stone", 1, 1, '*'), Loc::new(0, 0));
+        assert!(result.is_ok());
+        assert!(pack.check_invariants().is_ok());
+
+        let coin = PackedItem::new(Loc::new(0, 1), Item::new("coin", 1, 1, 'o'));
+        pack.items.insert(Loc::new(0, 2), coin);
+
+        let report = pack.check_invariants();
+        assert!(matches!(
+            &report.violations[..],
+            [Violation::MisplacedKey { key, item }]
+                if *key == Loc::new(0, 2) && item.name() == "coin"
+        ));
+    }
+
+    #[test]
+    fn equal_packs_hash_alike() {
+        let mut first = MapPack::new(1, 3);
+        let mut second = MapPack::new(1, 3).with_name_policy(NamePolicy::AutoSuffix);
+        assert!(first
+            .add_item(Item::new("stone", 1, 1, '*'), Loc::new(0, 2))
+            .is_ok());
+        assert!(second
+            .add_item(Item::new("stone", 1, 1, '*'), Loc::new(0, 0))
+            .is_ok());
+        assert!(second.move_item(Loc::new(0, 0), Loc::new(0, 2)).is_ok());
+
+        assert_eq!(first, second);
+        let states: std::collections::HashSet<MapPack> = [first.clone(), second].into();
+        assert_eq!(states.len(), 1);
+        assert!(states.contains(&first));
+        assert_ne!(first, MapPack::new(1, 3));
+    }
 }
@@ -0,0 +1,278 @@
+use crate::items::{Loc, PackedItem};
+use crate::packs::{Diff, Pack, Snapshot};
+
+use std::collections::HashMap;
+
+/// Which version of the pack a placement in a merge came from.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Side {
+    Base,
+    Ours,
+    Theirs,
+}
+
+/// An item where one version of the pack would have it.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Proposed {
+    pub side: Side,
+    pub item: PackedItem,
+}
+
+/// A change that could not be merged automatically. Whatever a conflict
+/// involves is left as it was in the base.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Conflict {
+    /// Both sides moved or turned the same item, differently.
+    BothChanged {
+        base: PackedItem,
+        ours: PackedItem,
+        theirs: PackedItem,
+    },
+    /// One side removed an item the other side moved or turned.
+    RemovedAndChanged { base: PackedItem, changed: Proposed },
+    /// Two items would end up in overlapping spots. Changed items go back
+    /// to where they were; added items are left out.
+    Overlap { first: Proposed, second: Proposed },
+}
+
+/// The outcome of a three-way merge: every change that could be made, and
+/// the conflicts that kept the rest out.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Merge {
+    pub merged: Snapshot,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl Merge {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// What a side did to each item of the base, keyed by the item's base
+/// anchor: `None` if it was removed, otherwise the item as it is now.
+fn fates(diff: &Diff) -> HashMap<Loc, Option<PackedItem>> {
+    let mut fates = HashMap::new();
+    for packed_item in &diff.removed {
+        fates.insert(packed_item.loc(), None);
+    }
+    for relocation in diff.moved.iter().chain(&diff.reoriented) {
+        fates.insert(relocation.old_loc(), Some(relocation.after.clone()));
+    }
+    fates
+}
+
+/// Merge two edited copies of `base`. Changes made by only one side, or by
+/// both sides alike, are applied; the rest are reported as conflicts.
+pub fn merge(base: &dyn Pack, ours: &dyn Pack, theirs: &dyn Pack) -> Result<Merge, String> {
+    let size = (base.rows(), base.cols());
+    if (ours.rows(), ours.cols()) != size || (theirs.rows(), theirs.cols()) != size {
+        return Err("The packs differ in size.".to_string());
+    }
+    let base = Snapshot::of(base);
+    let ours_diff = base.diff(&Snapshot::of(ours));
+    let theirs_diff = base.diff(&Snapshot::of(theirs));
+    let ours_fates = fates(&ours_diff);
+    let theirs_fates = fates(&theirs_diff);
+
+    let mut conflicts = Vec::new();
+    // Each placement keeps the base item it replaces, to fall back on. Base
+    // placements come first, then ours, then theirs.
+    let mut kept: Vec<(Proposed, Option<PackedItem>)> = Vec::new();
+    let mut changed: Vec<(Proposed, Option<PackedItem>)> = Vec::new();
+    let mut changed_by_them: Vec<(Proposed, Option<PackedItem>)> = Vec::new();
+    for packed_item in base.iter() {
+        let proposed = |side, item: &PackedItem| Proposed {
+            side,
+            item: item.clone(),
+        };
+        let fallback = Some(packed_item.clone());
+        match (
+            ours_fates.get(&packed_item.loc()),
+            theirs_fates.get(&packed_item.loc()),
+        ) {
+            (None, None) => kept.push((proposed(Side::Base, packed_item), None)),
+            (Some(None), None) | (None, Some(None)) | (Some(None), Some(None)) => {}
+            (Some(Some(after)), None) => changed.push((proposed(Side::Ours, after), fallback)),
+            (None, Some(Some(after))) => {
+                changed_by_them.push((proposed(Side::Theirs, after), fallback))
+            }
+            (Some(Some(ours)), Some(Some(theirs))) if ours == theirs => {
+                changed.push((proposed(Side::Ours, ours), fallback))
+            }
+            (Some(Some(ours)), Some(Some(theirs))) => {
+                conflicts.push(Conflict::BothChanged {
+                    base: packed_item.clone(),
+                    ours: ours.clone(),
+                    theirs: theirs.clone(),
+                });
+                kept.push((proposed(Side::Base, packed_item), None));
+            }
+            (Some(None), Some(Some(after))) => {
+                conflicts.push(Conflict::RemovedAndChanged {
+                    base: packed_item.clone(),
+                    changed: proposed(Side::Theirs, after),
+                });
+                kept.push((proposed(Side::Base, packed_item), None));
+            }
+            (Some(Some(after)), Some(None)) => {
+                conflicts.push(Conflict::RemovedAndChanged {
+                    base: packed_item.clone(),
+                    changed: proposed(Side::Ours, after),
+                });
+                kept.push((proposed(Side::Base, packed_item), None));
+            }
+        }
+    }
+    let added = |side, packed_item: &PackedItem| {
+        let item = packed_item.clone();
+        (Proposed { side, item }, None)
+    };
+    changed.extend(ours_diff.added.iter().map(|item| added(Side::Ours, item)));
+    // Both sides adding the same item in the same spot is one addition.
+    changed_by_them.extend(
+        theirs_diff
+            .added
+            .iter()
+            .filter(|item| !ours_diff.added.contains(item))
+            .map(|item| added(Side::Theirs, item)),
+    );
+
+    let mut placements = kept;
+    placements.extend(changed);
+    placements.extend(changed_by_them);
+    // Base placements never overlap each other, so reverting the changed
+    // side of every clash eventually leaves none.
+    loop {
+        let count = placements.len();
+        let clash = (0..count)
+            .flat_map(|i| (i + 1..count).map(move |j| (i, j)))
+            .find(|&(i, j)| placements[i].0.item.intersects(&placements[j].0.item));
+        let Some((i, j)) = clash else {
+            break;
+        };
+        conflicts.push(Conflict::Overlap {
+            first: placements[i].0.clone(),
+            second: placements[j].0.clone(),
+        });
+        for k in [j, i] {
+            if placements[k].0.side == Side::Base {
+                continue;
+            }
+            match placements[k].1.take() {
+                Some(item) => {
+                    placements[k].0 = Proposed {
+                        side: Side::Base,
+                        item,
+                    }
+                }
+                None => {
+                    placements.remove(k);
+                }
+            }
+        }
+    }
+
+    let items = placements
+        .into_iter()
+        .map(|(proposed, _)| proposed.item)
+        .collect();
+    Ok(Merge {
+        merged: Snapshot::from_items(size.0, size.1, items),
+        conflicts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::Item;
+    use crate::packs::{map_pack::MapPack, DensePack};
+
+    fn base() -> DensePack {
+        let mut pack = DensePack::new(2, 4);
+        for (name, col) in [("coin", 0), ("rock", 1), ("gem", 3)] {
+            assert!(pack
+                .add_item(Item::new(name, 1, 1, '*'), Loc::new(0, col))
+                .is_ok());
+        }
+        pack
+    }
+
+    fn copy(pack: &DensePack) -> MapPack {
+        pack.snapshot().to_pack(MapPack::new).unwrap()
+    }
+
+    #[test]
+    fn changes_from_both_sides_are_combined() {
+        let base = base();
+        let mut ours = copy(&base);
+        let mut theirs = copy(&base);
+        assert!(ours.move_item(Loc::new(0, 0), Loc::new(1, 0)).is_ok());
+        assert!(theirs.remove_item(Loc::new(0, 1)).is_ok());
+        assert!(theirs
+            .add_item(Item::new("apple", 1, 1, 'a'), Loc::new(1, 3))
+            .is_ok());
+
+        let merge = merge(&base, &ours, &theirs).unwrap();
+        assert!(merge.is_clean());
+        let merged: Vec<(String, Loc)> = merge
+            .merged
+            .iter()
+            .map(|item| (item.name().to_string(), item.loc()))
+            .collect();
+        assert_eq!(
+            merged,
+            vec![
+                ("gem".to_string(), Loc::new(0, 3)),
+                ("coin".to_string(), Loc::new(1, 0)),
+                ("apple".to_string(), Loc::new(1, 3)),
+            ]
+        );
+        let pack: DensePack = merge.merged.to_pack(DensePack::new).unwrap();
+        assert_eq!(pack.iter().count(), 3);
+    }
+
+    #[test]
+    fn items_moved_into_the_same_spot_conflict_and_stay_put() {
+        let base = base();
+        let mut ours = copy(&base);
+        let mut theirs = copy(&base);
+        assert!(ours.move_item(Loc::new(0, 0), Loc::new(1, 2)).is_ok());
+        assert!(theirs.move_item(Loc::new(0, 1), Loc::new(1, 2)).is_ok());
+
+        let merge = merge(&base, &ours, &theirs).unwrap();
+        assert!(matches!(
+            &merge.conflicts[..],
+            [Conflict::Overlap { first, second }]
+                if first.side == Side::Ours && first.item.name() == "coin"
+                    && second.side == Side::Theirs && second.item.name() == "rock"
+        ));
+        assert_eq!(merge.merged, base.snapshot());
+    }
+
+    #[test]
+    fn removing_an_item_the_other_side_moved_conflicts() {
+        let base = base();
+        let mut ours = copy(&base);
+        let mut theirs = copy(&base);
+        assert!(ours.remove_item(Loc::new(0, 3)).is_ok());
+        assert!(theirs.move_item(Loc::new(0, 3), Loc::new(1, 3)).is_ok());
+        assert!(ours.move_item(Loc::new(0, 0), Loc::new(1, 0)).is_ok());
+        assert!(theirs.move_item(Loc::new(0, 0), Loc::new(1, 1)).is_ok());
+
+        let merge = merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(merge.conflicts.len(), 2);
+        assert!(matches!(
+            &merge.conflicts[0],
+            Conflict::BothChanged { ours, theirs, .. }
+                if ours.loc() == Loc::new(1, 0) && theirs.loc() == Loc::new(1, 1)
+        ));
+        assert!(matches!(
+            &merge.conflicts[1],
+            Conflict::RemovedAndChanged { base, changed }
+                if base.name() == "gem" && changed.side == Side::Theirs
+        ));
+        assert_eq!(merge.merged, base.snapshot());
+    }
+}
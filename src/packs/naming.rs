@@ -0,0 +1,72 @@
+/// How a pack treats an item whose name is already taken by a packed item.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum NamePolicy {
+    /// Any number of packed items may share a name. Name-based lookups
+    /// refuse to pick between them.
+    #[default]
+    AllowDuplicates,
+    /// Adding an item under a name that is already packed is an error.
+    RejectDuplicates,
+    /// A colliding name gets the next free instance suffix:
+    /// "torch", "torch#2", "torch#3", ...
+    AutoSuffix,
+}
+
+impl NamePolicy {
+    /// Decide which name an incoming item is packed under, given a predicate
+    /// telling whether a name is already taken in the pack.
+    pub(crate) fn resolve<F>(&self, name: &str, taken: F) -> Result<String, String>
+    where
+        F: Fn(&str) -> bool,
+    {
+        if !taken(name) {
+            return Ok(name.to_string());
+        }
+
+        match self {
+            NamePolicy::AllowDuplicates => Ok(name.to_string()),
+            NamePolicy::RejectDuplicates => {
+                Err(format!("An item named {} is already packed.", name))
+            }
+            NamePolicy::AutoSuffix => {
+                let mut instance = 2;
+                loop {
+                    let candidate = format!("{}#{}", name, instance);
+                    if !taken(&candidate) {
+                        return Ok(candidate);
+                    }
+                    instance += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn free_name_is_kept_under_every_policy() {
+        for policy in [
+            NamePolicy::AllowDuplicates,
+            NamePolicy::RejectDuplicates,
+            NamePolicy::AutoSuffix,
+        ] {
+            assert_eq!(policy.resolve("torch", |_| false), Ok("torch".to_string()));
+        }
+    }
+
+    #[test]
+    fn reject_duplicates_is_an_error_for_taken_name() {
+        let result = NamePolicy::RejectDuplicates.resolve("torch", |name| name == "torch");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn auto_suffix_picks_next_free_instance() {
+        let taken = ["torch", "torch#2"];
+        let result = NamePolicy::AutoSuffix.resolve("torch", |name| taken.contains(&name));
+        assert_eq!(result, Ok("torch#3".to_string()));
+    }
+}
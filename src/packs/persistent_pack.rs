@@ -0,0 +1,302 @@
+use crate::behavior::Motion;
+use crate::items::{Item, Loc, PackedItem};
+use crate::packs::{Items, Pack, PackMut, RenderMode};
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+/// The items anchored in one row of the pack, ordered by column.
+type Line = Rc<Vec<Rc<PackedItem>>>;
+
+/// An immutable pack. Adding, moving, transposing and removing return a new
+/// version and leave the old one as it was. Versions share every row and
+/// item a change does not touch, so cloning one copies a single pointer and
+/// a change copies only the rows it touches.
+///
+/// Placement follows the rules of `DensePack`, behavior hooks included.
+/// Names are not checked.
+#[derive(Debug, Clone)]
+pub struct PersistentPack {
+    rows: u32,
+    cols: u32,
+    lines: Rc<Vec<Line>>,
+}
+
+impl PersistentPack {
+    pub fn new(rows: u32, cols: u32) -> Self {
+        let rows = rows.max(1);
+        let cols = cols.max(1);
+        // Every row starts out sharing one empty line.
+        let empty: Line = Rc::new(Vec::new());
+        PersistentPack {
+            rows,
+            cols,
+            lines: Rc::new((0..rows).map(|_| Rc::clone(&empty)).collect()),
+        }
+    }
+
+    /// A persistent copy of any pack's contents.
+    pub fn from_pack(pack: &dyn Pack) -> Self {
+        let mut lines = vec![Vec::new(); pack.rows() as usize];
+        for packed_item in pack.iter() {
+            lines[packed_item.row() as usize].push(Rc::new(packed_item.clone()));
+        }
+        PersistentPack {
+            rows: pack.rows(),
+            cols: pack.cols(),
+            lines: Rc::new(lines.into_iter().map(Rc::new).collect()),
+        }
+    }
+
+    /// Build a mutable pack holding this version's items, creating the
+    /// empty pack with `new`. Items are restored as they are, without hooks.
+    pub fn to_pack<P, F>(&self, new: F) -> Result<P, String>
+    where
+        P: PackMut,
+        F: FnOnce(u32, u32) -> P,
+    {
+        let mut pack = new(self.rows, self.cols);
+        for packed_item in self.iter() {
+            pack.restore(packed_item.clone())?;
+        }
+        Ok(pack)
+    }
+
+    /// This version with `packed_item` anchored where it says.
+    fn with(&self, packed_item: Rc<PackedItem>) -> Self {
+        let row = packed_item.row() as usize;
+        let mut line = self.lines[row].as_ref().clone();
+        let idx = line.partition_point(|other| other.col() < packed_item.col());
+        line.insert(idx, packed_item);
+        let mut lines = self.lines.as_ref().clone();
+        lines[row] = Rc::new(line);
+        PersistentPack {
+            lines: Rc::new(lines),
+            ..*self
+        }
+    }
+
+    /// This version without the item covering `loc`, and that item.
+    fn without(&self, loc: &Loc) -> Option<(Self, Rc<PackedItem>)> {
+        let anchor = self.item_at(loc)?.loc();
+        let row = anchor.row() as usize;
+        let mut line = self.lines[row].as_ref().clone();
+        let idx = line
+            .iter()
+            .position(|packed_item| packed_item.col() == anchor.col())?;
+        let packed_item = line.remove(idx);
+        let mut lines = self.lines.as_ref().clone();
+        lines[row] = Rc::new(line);
+        let rest = PersistentPack {
+            lines: Rc::new(lines),
+            ..*self
+        };
+        Some((rest, packed_item))
+    }
+
+    pub fn add(&self, item: Item, loc: Loc) -> Result<Self, String> {
+        let mut item = item;
+        let mut loc = loc;
+        if let Some(behavior) = item.behavior().cloned() {
+            behavior.on_add(&mut item, &mut loc, self)?;
+        }
+        let tentative = PackedItem::new(loc, item);
+        if !self.placement_is_valid(&tentative) {
+            return Err("Invalid item placement".to_string());
+        }
+        Ok(self.with(Rc::new(tentative)))
+    }
+
+    /// The version without the item covering `loc`, and that item, unless
+    /// its behavior refuses removal.
+    pub fn remove(&self, loc: Loc) -> Result<(Self, PackedItem), String> {
+        let packed_item = match self.item_at(&loc) {
+            Some(packed_item) => packed_item,
+            None => return Err("No item at the given location".to_string()),
+        };
+        if let Some(behavior) = packed_item.behavior() {
+            behavior.on_remove(packed_item, self)?;
+        }
+        let (rest, packed_item) = self.without(&loc).unwrap();
+        Ok((rest, packed_item.as_ref().clone()))
+    }
+
+    /// Move the item covering `src` so it is anchored at `dst`.
+    pub fn move_item(&self, src: Loc, dst: Loc) -> Result<Self, String> {
+        self.apply_motion(
+            src,
+            Motion {
+                dst,
+                transpose: false,
+            },
+        )
+    }
+
+    pub fn transpose(&self, loc: Loc) -> Result<Self, String> {
+        let dst = match self.item_at(&loc) {
+            Some(packed_item) => packed_item.loc(),
+            None => return Err("No item at the given location".to_string()),
+        };
+        self.apply_motion(
+            loc,
+            Motion {
+                dst,
+                transpose: true,
+            },
+        )
+    }
+
+    fn apply_motion(&self, src: Loc, motion: Motion) -> Result<Self, String> {
+        let packed_item = match self.item_at(&src) {
+            Some(packed_item) => packed_item,
+            None => return Err("No item at the given location".to_string()),
+        };
+        let mut motion = motion;
+        if let Some(behavior) = packed_item.behavior() {
+            behavior.on_move(packed_item, &mut motion, self)?;
+        }
+        if !self.motion_is_valid(packed_item, motion) {
+            if motion.transpose {
+                return Err("Invalid transposition.".to_string());
+            }
+            return Err("Invalid move".to_string());
+        }
+
+        let mut moved = packed_item.clone();
+        moved.move_to(motion.dst);
+        if motion.transpose {
+            moved.transpose();
+        }
+        let (rest, _) = self.without(&src).unwrap();
+        Ok(rest.with(Rc::new(moved)))
+    }
+}
+
+impl Pack for PersistentPack {
+    fn rows(&self) -> u32 {
+        self.rows
+    }
+
+    fn cols(&self) -> u32 {
+        self.cols
+    }
+
+    fn packed_items(&self) -> Items<'_> {
+        Box::new(
+            self.lines
+                .iter()
+                .flat_map(|line| line.iter().map(|packed_item| packed_item.as_ref())),
+        )
+    }
+
+    fn item_at(&self, loc: &Loc) -> Option<&PackedItem> {
+        self.packed_items()
+            .find(|packed_item| packed_item.contains(loc))
+    }
+
+    /// Lines are kept in reading order already.
+    fn iter(&self) -> Items<'_> {
+        self.packed_items()
+    }
+}
+
+impl fmt::Display for PersistentPack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.render(RenderMode::Plain).fmt(f)
+    }
+}
+
+/// Compared and hashed like the mutable packs: by size and by which items
+/// sit where.
+impl PartialEq for PersistentPack {
+    fn eq(&self, other: &Self) -> bool {
+        (self.rows, self.cols) == (other.rows, other.cols)
+            && (Rc::ptr_eq(&self.lines, &other.lines) || self.iter().eq(other.iter()))
+    }
+}
+
+impl Eq for PersistentPack {}
+
+impl Hash for PersistentPack {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.rows, self.cols).hash(state);
+        for packed_item in self.iter() {
+            packed_item.hash(state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packs::{map_pack::MapPack, DensePack};
+
+    #[test]
+    fn changes_make_new_versions_and_leave_old_ones_alone() {
+        let empty = PersistentPack::new(2, 3);
+        let one = empty
+            .add(Item::new("rope", 1, 2, 'r'), Loc::new(0, 0))
+            .unwrap();
+        let moved = one.move_item(Loc::new(0, 1), Loc::new(1, 1)).unwrap();
+        let turned = moved.transpose(Loc::new(1, 2)).unwrap_err();
+
+        assert_eq!(empty.iter().count(), 0);
+        assert_eq!(one.to_string(), "|r|r| |\n| | | |\n");
+        assert_eq!(moved.to_string(), "| | | |\n| |r|r|\n");
+        assert_eq!(turned, "Invalid transposition.");
+
+        let (rest, rope) = moved.remove(Loc::new(1, 2)).unwrap();
+        assert_eq!(rope.name(), "rope");
+        assert_eq!(rest, empty);
+        assert!(moved.remove(Loc::new(0, 0)).is_err());
+    }
+
+    #[test]
+    fn placement_follows_dense_pack_rules() {
+        let pack = PersistentPack::new(2, 2)
+            .add(Item::new("coin", 1, 1, 'o'), Loc::new(0, 0))
+            .unwrap();
+        assert!(pack
+            .add(Item::new("gem", 1, 1, 'g'), Loc::new(0, 0))
+            .is_err());
+        assert!(pack
+            .add(Item::new("pole", 3, 1, 'p'), Loc::new(0, 1))
+            .is_err());
+        assert!(pack.move_item(Loc::new(0, 0), Loc::new(2, 0)).is_err());
+        assert!(pack.move_item(Loc::new(1, 1), Loc::new(0, 1)).is_err());
+    }
+
+    #[test]
+    fn untouched_rows_and_items_are_shared() {
+        let base = PersistentPack::new(3, 3)
+            .add(Item::new("coin", 1, 1, 'o'), Loc::new(0, 0))
+            .unwrap()
+            .add(Item::new("gem", 1, 1, 'g'), Loc::new(2, 2))
+            .unwrap();
+        let branch = base.move_item(Loc::new(2, 2), Loc::new(1, 2)).unwrap();
+
+        assert!(Rc::ptr_eq(&base.lines[0], &branch.lines[0]));
+        assert!(!Rc::ptr_eq(&base.lines[2], &branch.lines[2]));
+        assert!(Rc::ptr_eq(&base.lines[0][0], &branch.lines[0][0]));
+        assert!(Rc::ptr_eq(&base.clone().lines, &base.lines));
+    }
+
+    #[test]
+    fn converts_to_and_from_mutable_packs() {
+        let mut dense = DensePack::new(2, 3);
+        assert!(dense
+            .add_item(Item::new("rope", 1, 2, 'r'), Loc::new(1, 1))
+            .is_ok());
+        assert!(dense
+            .add_item(Item::new("coin", 1, 1, 'o'), Loc::new(0, 2))
+            .is_ok());
+
+        let persistent = PersistentPack::from_pack(&dense);
+        assert_eq!(persistent.to_string(), dense.to_string());
+        let back: DensePack = persistent.to_pack(DensePack::new).unwrap();
+        assert_eq!(back, dense);
+        let map: MapPack = persistent.to_pack(MapPack::new).unwrap();
+        assert_eq!(PersistentPack::from_pack(&map), persistent);
+    }
+}
@@ -0,0 +1,206 @@
+use crate::behavior::Motion;
+use crate::items::{Loc, Rect};
+use crate::packs::{Displacement, PackMut};
+
+/// An item's footprint while a push is being planned.
+struct Block {
+    name: String,
+    from: Loc,
+    rect: Rect,
+    pinned: bool,
+}
+
+/// Every anchor in the pack where a `rows` x `cols` block fits, nearest to
+/// `origin` first, ties in reading order.
+fn anchors_near(pack_rows: u32, pack_cols: u32, rows: u32, cols: u32, origin: Loc) -> Vec<Loc> {
+    if rows > pack_rows || cols > pack_cols {
+        return Vec::new();
+    }
+    let mut anchors: Vec<Loc> = (0..=pack_rows - rows)
+        .flat_map(|r| (0..=pack_cols - cols).map(move |c| Loc::new(r, c)))
+        .collect();
+    anchors.sort_by_key(|loc| {
+        let distance = loc.row().abs_diff(origin.row()) + loc.col().abs_diff(origin.col());
+        (distance, *loc)
+    });
+    anchors
+}
+
+/// The indices of the blocks other than `skip` that overlap `rect`.
+fn overlapping(blocks: &[Block], skip: usize, rect: &Rect) -> Vec<usize> {
+    (0..blocks.len())
+        .filter(|&i| i != skip && blocks[i].rect.intersects(rect))
+        .collect()
+}
+
+pub(crate) fn move_with_push<P: PackMut + ?Sized>(
+    pack: &mut P,
+    src: Loc,
+    dst: Loc,
+) -> Result<Vec<Displacement>, String> {
+    let Some(mover) = pack.item_at(&src) else {
+        return Err(format!("No item at {}", src));
+    };
+    let mover_loc = mover.loc();
+
+    let mut blocks: Vec<Block> = pack
+        .iter()
+        .map(|packed_item| Block {
+            name: packed_item.name().to_string(),
+            from: packed_item.loc(),
+            rect: packed_item.rect(),
+            pinned: packed_item.loc() == mover_loc,
+        })
+        .collect();
+    let moving = blocks
+        .iter()
+        .position(|block| block.pinned)
+        .expect("the moving item is in the pack");
+    let rect = Rect::new(dst, blocks[moving].rect.rows(), blocks[moving].rect.cols());
+    if dst.row() + rect.rows() > pack.rows() || dst.col() + rect.cols() > pack.cols() {
+        return Err(format!("The item does not fit at {}", dst));
+    }
+    blocks[moving].rect = rect;
+
+    // Each pushed item is pinned where it lands, so nothing is pushed twice
+    // and the chain always ends.
+    let mut queue = overlapping(&blocks, moving, &rect);
+    while let Some(i) = queue.pop() {
+        blocks[i].pinned = true;
+        let (rows, cols) = (blocks[i].rect.rows(), blocks[i].rect.cols());
+        let mut spot: Option<(Loc, Vec<usize>)> = None;
+        for loc in anchors_near(pack.rows(), pack.cols(), rows, cols, blocks[i].from) {
+            let candidate = Rect::new(loc, rows, cols);
+            let hit = overlapping(&blocks, i, &candidate);
+            if hit.iter().any(|&j| blocks[j].pinned) {
+                continue;
+            }
+            // A free spot beats any spot that pushes something further.
+            if hit.is_empty() {
+                spot = Some((loc, hit));
+                break;
+            }
+            if spot.is_none() {
+                spot = Some((loc, hit));
+            }
+        }
+        let Some((loc, hit)) = spot else {
+            return Err(format!(
+                "There is no room to push the {} aside.",
+                blocks[i].name
+            ));
+        };
+        blocks[i].rect = Rect::new(loc, rows, cols);
+        for j in hit {
+            if !queue.contains(&j) {
+                queue.push(j);
+            }
+        }
+    }
+
+    let mut motions = Vec::new();
+    let mut displacements = Vec::new();
+    for (i, block) in blocks.iter().enumerate() {
+        if block.rect.loc() == block.from {
+            continue;
+        }
+        motions.push((
+            block.from,
+            Motion {
+                dst: block.rect.loc(),
+                transpose: false,
+            },
+        ));
+        if i != moving {
+            displacements.push(Displacement {
+                name: block.name.clone(),
+                from: block.from,
+                to: block.rect.loc(),
+            });
+        }
+    }
+    pack.apply_motions(&motions)?;
+    Ok(displacements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::Item;
+    use crate::packs::{map_pack::MapPack, DensePack, Pack};
+
+    #[test]
+    fn pebble_is_nudged_aside() {
+        // |b|b| |      | |b|b|
+        // |b|b|.|  ->  |.|b|b|
+        let mut pack = DensePack::new(2, 3);
+        assert!(pack
+            .add_item(Item::new("box", 2, 2, 'b'), Loc::new(0, 0))
+            .is_ok());
+        assert!(pack
+            .add_item(Item::new("pebble", 1, 1, '.'), Loc::new(1, 2))
+            .is_ok());
+
+        let pushed = pack.move_with_push(Loc::new(0, 0), Loc::new(0, 1));
+        assert_eq!(
+            pushed,
+            Ok(vec![Displacement {
+                name: "pebble".to_string(),
+                from: Loc::new(1, 2),
+                to: Loc::new(1, 0),
+            }])
+        );
+        assert_eq!(pack.item_at(&Loc::new(1, 2)).unwrap().name(), "box");
+    }
+
+    #[test]
+    fn pushes_chain_when_there_is_no_free_spot() {
+        // |a|b|      |b|a|
+        // |a|m|  ->  |m|a|
+        let mut pack = MapPack::new(2, 2);
+        assert!(pack
+            .add_item(Item::new("a", 2, 1, 'a'), Loc::new(0, 0))
+            .is_ok());
+        assert!(pack
+            .add_item(Item::new("b", 1, 1, 'b'), Loc::new(0, 1))
+            .is_ok());
+        assert!(pack
+            .add_item(Item::new("m", 1, 1, 'm'), Loc::new(1, 1))
+            .is_ok());
+
+        let mut pushed = pack.move_with_push(Loc::new(1, 1), Loc::new(1, 0)).unwrap();
+        pushed.sort_by(|x, y| x.name.cmp(&y.name));
+        assert_eq!(
+            pushed,
+            vec![
+                Displacement {
+                    name: "a".to_string(),
+                    from: Loc::new(0, 0),
+                    to: Loc::new(0, 1),
+                },
+                Displacement {
+                    name: "b".to_string(),
+                    from: Loc::new(0, 1),
+                    to: Loc::new(0, 0),
+                },
+            ]
+        );
+        assert_eq!(pack.item_at(&Loc::new(1, 0)).unwrap().name(), "m");
+    }
+
+    #[test]
+    fn push_without_room_changes_nothing() {
+        // |w|w|m|  There is nowhere for the wall to go.
+        let mut pack = DensePack::new(1, 3);
+        assert!(pack
+            .add_item(Item::new("wall", 1, 2, 'w'), Loc::new(0, 0))
+            .is_ok());
+        assert!(pack
+            .add_item(Item::new("m", 1, 1, 'm'), Loc::new(0, 2))
+            .is_ok());
+        let before = pack.to_string();
+
+        assert!(pack.move_with_push(Loc::new(0, 2), Loc::new(0, 1)).is_err());
+        assert_eq!(pack.to_string(), before);
+    }
+}
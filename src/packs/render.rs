@@ -0,0 +1,389 @@
+use crate::items::{Color, Loc, PackedItem, Rect};
+use crate::packs::Pack;
+
+use std::fmt;
+
+use unicode_width::UnicodeWidthStr;
+
+/// How a pack's grid is drawn.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum RenderMode {
+    /// One symbol per cell, cells separated by `|`.
+    #[default]
+    Plain,
+    /// Box-drawing outlines around every item. Empty cells show a dot.
+    Bordered,
+}
+
+/// A pack paired with a render mode, ready to be displayed.
+///
+/// Cells are as wide as the widest symbol in the pack, so two-column
+/// glyphs (CJK, most emoji) keep the grid aligned. Item colors are only
+/// emitted as ANSI escapes once turned on with `with_color`.
+pub struct Rendered<'a, P: Pack + ?Sized> {
+    pack: &'a P,
+    mode: RenderMode,
+    color: bool,
+}
+
+impl<'a, P: Pack + ?Sized> Rendered<'a, P> {
+    pub fn new(pack: &'a P, mode: RenderMode) -> Self {
+        Rendered {
+            pack,
+            mode,
+            color: false,
+        }
+    }
+
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+}
+
+/// What covers a single cell. Cells belonging to the same item share its
+/// anchor `Loc`, which is unique because items never overlap.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum Occupant {
+    Outside,
+    Empty,
+    Item(Loc),
+}
+
+/// Whether a border runs between two neighbouring cells.
+fn boundary(a: Occupant, b: Occupant) -> bool {
+    a != b && !(a == Occupant::Empty && b == Occupant::Empty)
+}
+
+/// The box-drawing character joining the borders that meet at a grid point.
+fn junction(up: bool, down: bool, left: bool, right: bool) -> char {
+    match (up, down, left, right) {
+        (false, false, false, false) => ' ',
+        (true, false, false, false) => '╵',
+        (false, true, false, false) => '╷',
+        (false, false, true, false) => '╴',
+        (false, false, false, true) => '╶',
+        (true, true, false, false) => '│',
+        (false, false, true, true) => '─',
+        (false, true, false, true) => '┌',
+        (false, true, true, false) => '┐',
+        (true, false, false, true) => '└',
+        (true, false, true, false) => '┘',
+        (true, true, false, true) => '├',
+        (true, true, true, false) => '┤',
+        (false, true, true, true) => '┬',
+        (true, false, true, true) => '┴',
+        (true, true, true, true) => '┼',
+    }
+}
+
+/// The SGR parameters selecting `color` as foreground or background.
+fn sgr(color: Color, background: bool) -> String {
+    let base = if background { 40 } else { 30 };
+    match color {
+        Color::Black => base.to_string(),
+        Color::Red => (base + 1).to_string(),
+        Color::Green => (base + 2).to_string(),
+        Color::Yellow => (base + 3).to_string(),
+        Color::Blue => (base + 4).to_string(),
+        Color::Magenta => (base + 5).to_string(),
+        Color::Cyan => (base + 6).to_string(),
+        Color::White => (base + 7).to_string(),
+        Color::Ansi(n) => format!("{};5;{}", base + 8, n),
+        Color::Rgb(r, g, b) => format!("{};2;{};{};{}", base + 8, r, g, b),
+    }
+}
+
+/// One painted cell: who covers it and how it is drawn.
+#[derive(Debug, Clone)]
+pub(crate) struct Cell<'a> {
+    pub(crate) occupant: Occupant,
+    pub(crate) symbol: &'a str,
+    pub(crate) fg: Option<Color>,
+    pub(crate) bg: Option<Color>,
+    /// Drawn after the padded symbol, in the same colors.
+    pub(crate) marker: Option<char>,
+}
+
+impl<'a> Cell<'a> {
+    /// A cell showing `symbol` with no item and no styling.
+    pub(crate) fn blank(symbol: &'a str) -> Self {
+        Cell {
+            occupant: Occupant::Empty,
+            symbol,
+            fg: None,
+            bg: None,
+            marker: None,
+        }
+    }
+
+    /// A cell of `packed_item`, in its colors.
+    pub(crate) fn of(packed_item: &'a PackedItem) -> Self {
+        Cell {
+            occupant: Occupant::Item(packed_item.loc()),
+            symbol: packed_item.symbol(),
+            fg: packed_item.fg(),
+            bg: packed_item.bg(),
+            marker: None,
+        }
+    }
+}
+
+/// The cells of a pack-sized grid, painted one rectangle at a time and
+/// then drawn in one of the render modes.
+pub(crate) struct Grid<'a> {
+    cells: Vec<Vec<Cell<'a>>>,
+    /// The width of the widest symbol, which every cell is padded to.
+    width: usize,
+}
+
+impl<'a> Grid<'a> {
+    /// A grid of `blank` cells.
+    pub(crate) fn new(rows: u32, cols: u32, blank: Cell<'a>) -> Self {
+        let width = blank.symbol.width().max(1);
+        Grid {
+            cells: vec![vec![blank; cols as usize]; rows as usize],
+            width,
+        }
+    }
+
+    /// Cover every cell of `rect` inside the grid with `cell`. Later paint
+    /// covers earlier paint.
+    pub(crate) fn paint(&mut self, rect: Rect, cell: Cell<'a>) {
+        self.fit(cell.symbol);
+        for r in rect.row()..rect.row() + rect.rows() {
+            for c in rect.col()..rect.col() + rect.cols() {
+                if let Some(slot) = self
+                    .cells
+                    .get_mut(r as usize)
+                    .and_then(|row| row.get_mut(c as usize))
+                {
+                    *slot = cell.clone();
+                }
+            }
+        }
+    }
+
+    /// Widen every cell enough to hold `symbol`.
+    pub(crate) fn fit(&mut self, symbol: &str) {
+        self.width = self.width.max(symbol.width());
+    }
+
+    fn write_cell(&self, f: &mut fmt::Formatter, cell: &Cell, color: bool) -> fmt::Result {
+        let padding = " ".repeat(self.width.saturating_sub(cell.symbol.width()));
+        let mut params = Vec::new();
+        if color {
+            params.extend(cell.fg.map(|fg| sgr(fg, false)));
+            params.extend(cell.bg.map(|bg| sgr(bg, true)));
+        }
+        let marker = cell.marker.map(String::from).unwrap_or_default();
+        if params.is_empty() {
+            return write!(f, "{}{}{}", cell.symbol, padding, marker);
+        }
+        write!(
+            f,
+            "\x1b[{}m{}{}{}\x1b[0m",
+            params.join(";"),
+            cell.symbol,
+            padding,
+            marker
+        )
+    }
+
+    /// Draw one symbol per cell, cells separated by `|`.
+    pub(crate) fn fmt_plain(&self, f: &mut fmt::Formatter, color: bool) -> fmt::Result {
+        const SEP: char = '|';
+        for row in &self.cells {
+            for cell in row {
+                write!(f, "{}", SEP)?;
+                self.write_cell(f, cell, color)?;
+            }
+            writeln!(f, "{}", SEP)?;
+        }
+        Ok(())
+    }
+
+    /// Draw box-drawing outlines between cells of different occupants.
+    pub(crate) fn fmt_bordered(&self, f: &mut fmt::Formatter, color: bool) -> fmt::Result {
+        let rows = self.cells.len();
+        let cols = self.cells.first().map_or(0, |row| row.len());
+        let width = self.width;
+        let cells = &self.cells;
+
+        // Signed lookup so the cells just past each edge read as outside.
+        let at = |r: isize, c: isize| -> Occupant {
+            if r < 0 || c < 0 || r >= rows as isize || c >= cols as isize {
+                return Occupant::Outside;
+            }
+            cells[r as usize][c as usize].occupant
+        };
+
+        for r in 0..=rows as isize {
+            // The border line above row r.
+            for c in 0..=cols as isize {
+                let up = r > 0 && boundary(at(r - 1, c - 1), at(r - 1, c));
+                let down = r < rows as isize && boundary(at(r, c - 1), at(r, c));
+                let left = c > 0 && boundary(at(r - 1, c - 1), at(r, c - 1));
+                let right = c < cols as isize && boundary(at(r - 1, c), at(r, c));
+                write!(f, "{}", junction(up, down, left, right))?;
+                if c < cols as isize {
+                    let edge = if right { "─" } else { " " };
+                    write!(f, "{}", edge.repeat(width))?;
+                }
+            }
+            writeln!(f)?;
+
+            if r == rows as isize {
+                break;
+            }
+
+            // The cells of row r with the borders between them.
+            for c in 0..=cols as isize {
+                let edge = if boundary(at(r, c - 1), at(r, c)) {
+                    '│'
+                } else {
+                    ' '
+                };
+                write!(f, "{}", edge)?;
+                if c < cols as isize {
+                    self.write_cell(f, &cells[r as usize][c as usize], color)?;
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl<P: Pack + ?Sized> Rendered<'_, P> {
+    /// Paint every cell with its occupant once, up front.
+    fn grid(&self, empty: &'static str) -> Grid<'_> {
+        let mut grid = Grid::new(self.pack.rows(), self.pack.cols(), Cell::blank(empty));
+        for packed_item in self.pack.packed_items() {
+            grid.paint(packed_item.rect(), Cell::of(packed_item));
+        }
+        grid
+    }
+}
+
+impl<P: Pack + ?Sized> fmt::Display for Rendered<'_, P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mode {
+            RenderMode::Plain => self.grid(" ").fmt_plain(f, self.color),
+            RenderMode::Bordered => self.grid("·").fmt_bordered(f, self.color),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::Item;
+    use crate::packs::{map_pack::MapPack, DensePack};
+
+    #[test]
+    fn plain_render_separates_cells_with_bars() {
+        let mut pack = DensePack::new(1, 3);
+        let result = pack.add_item(Item::new("stick", 1, 2, '*'), Loc::new(0, 0));
+        assert!(result.is_ok());
+
+        assert_eq!(pack.render(RenderMode::Plain).to_string(), "|*|*| |\n");
+    }
+
+    #[test]
+    fn bordered_render_outlines_adjacent_items_with_the_same_symbol() {
+        let mut pack = DensePack::new(1, 3);
+        let result = pack.add_item(Item::new("stone", 1, 1, '*'), Loc::new(0, 0));
+        assert!(result.is_ok());
+        let result = pack.add_item(Item::new("stone", 1, 1, '*'), Loc::new(0, 1));
+        assert!(result.is_ok());
+
+        let expected = "\
+┌─┬─┬─┐
+│*│*│·│
+└─┴─┴─┘
+";
+        assert_eq!(pack.render(RenderMode::Bordered).to_string(), expected);
+    }
+
+    #[test]
+    fn bordered_render_merges_cells_of_one_item_and_of_empty_space() {
+        let mut pack = MapPack::new(3, 3);
+        let result = pack.add_item(Item::new("stone", 2, 2, '*'), Loc::new(0, 0));
+        assert!(result.is_ok());
+
+        let expected = "\
+┌───┬─┐
+│* *│·│
+│   │ │
+│* *│·│
+├───┘ │
+│· · ·│
+└─────┘
+";
+        assert_eq!(pack.render(RenderMode::Bordered).to_string(), expected);
+    }
+
+    #[test]
+    fn bordered_render_lines_are_equally_wide() {
+        let mut pack = DensePack::new(4, 5);
+        let result = pack.add_item(Item::new("torch", 1, 3, '&'), Loc::new(1, 1));
+        assert!(result.is_ok());
+
+        let rendered = pack.render(RenderMode::Bordered).to_string();
+        for line in rendered.lines() {
+            assert_eq!(line.chars().count(), 11);
+        }
+    }
+
+    #[test]
+    fn wide_symbols_widen_every_cell() {
+        let mut pack = DensePack::new(1, 3);
+        let result = pack.add_item(Item::new("lantern", 1, 1, '灯'), Loc::new(0, 0));
+        assert!(result.is_ok());
+        let result = pack.add_item(Item::new("stone", 1, 1, '*'), Loc::new(0, 1));
+        assert!(result.is_ok());
+
+        let plain = pack.render(RenderMode::Plain).with_color(false).to_string();
+        assert_eq!(plain, "|灯|* |  |\n");
+
+        let bordered = pack
+            .render(RenderMode::Bordered)
+            .with_color(false)
+            .to_string();
+        let expected = "\
+┌──┬──┬──┐
+│灯│* │· │
+└──┴──┴──┘
+";
+        assert_eq!(bordered, expected);
+    }
+
+    #[test]
+    fn multi_code_point_symbols_are_kept_whole() {
+        let mut pack = MapPack::new(1, 1);
+        let heart = Item::new("heart", 1, 1, '?').with_symbol("\u{2764}\u{fe0f}");
+        let result = pack.add_item(heart, Loc::new(0, 0));
+        assert!(result.is_ok());
+
+        let plain = pack.render(RenderMode::Plain).with_color(false).to_string();
+        assert_eq!(plain, "|\u{2764}\u{fe0f}|\n");
+    }
+
+    #[test]
+    fn colors_are_emitted_only_when_enabled() {
+        let mut pack = DensePack::new(1, 1);
+        let ruby = Item::new("ruby", 1, 1, '*')
+            .with_fg(Color::Red)
+            .with_bg(Color::Rgb(1, 2, 3));
+        let result = pack.add_item(ruby, Loc::new(0, 0));
+        assert!(result.is_ok());
+
+        let colored = pack.render(RenderMode::Plain).with_color(true).to_string();
+        assert_eq!(colored, "|\x1b[31;48;2;1;2;3m*\x1b[0m|\n");
+
+        let uncolored = pack.render(RenderMode::Plain).with_color(false).to_string();
+        assert_eq!(uncolored, "|*|\n");
+        assert_eq!(pack.render(RenderMode::Plain).to_string(), uncolored);
+    }
+}
@@ -0,0 +1,227 @@
+use crate::items::{Item, Loc, Rect};
+use crate::packs::PackMut;
+
+use std::time::{Duration, Instant};
+
+/// Identifies a reservation so it can be released or fulfilled.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct ReservationId(u64);
+
+/// Cells held for an item that is on its way, such as one being dragged.
+/// Nothing else can be placed over them until the reservation is released,
+/// fulfilled or expires.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Reservation {
+    id: ReservationId,
+    rect: Rect,
+    item: String,
+    expires: Option<Instant>,
+}
+
+impl Reservation {
+    pub fn id(&self) -> ReservationId {
+        self.id
+    }
+
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    /// The name of the item the cells are held for.
+    pub fn item(&self) -> &str {
+        &self.item
+    }
+
+    /// When the reservation lapses, if ever.
+    pub fn expires(&self) -> Option<Instant> {
+        self.expires
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires.is_some_and(|expires| now >= expires)
+    }
+}
+
+/// The reservations held on a pack. Expired ones stop counting at once and
+/// are dropped the next time a reservation is made.
+#[derive(Debug, Clone, Default)]
+pub struct Reservations {
+    held: Vec<Reservation>,
+    next_id: u64,
+}
+
+impl Reservations {
+    /// The reservations that have not expired.
+    pub fn active(&self) -> impl Iterator<Item = &Reservation> {
+        let now = Instant::now();
+        self.held
+            .iter()
+            .filter(move |reservation| !reservation.is_expired(now))
+    }
+
+    pub fn get(&self, id: ReservationId) -> Option<&Reservation> {
+        self.active().find(|reservation| reservation.id == id)
+    }
+
+    /// Whether an active reservation covers any cell of `rect`.
+    pub(crate) fn blocks(&self, rect: &Rect) -> bool {
+        self.active()
+            .any(|reservation| reservation.rect.intersects(rect))
+    }
+
+    fn hold(&mut self, rect: Rect, item: &str, ttl: Option<Duration>) -> ReservationId {
+        self.expire();
+        let id = ReservationId(self.next_id);
+        self.next_id += 1;
+        self.held.push(Reservation {
+            id,
+            rect,
+            item: item.to_string(),
+            expires: ttl.map(|ttl| Instant::now() + ttl),
+        });
+        id
+    }
+
+    /// Take out a reservation that has not expired.
+    fn take(&mut self, id: ReservationId) -> Option<Reservation> {
+        let idx = self
+            .held
+            .iter()
+            .position(|reservation| reservation.id == id)?;
+        let reservation = self.held.remove(idx);
+        (!reservation.is_expired(Instant::now())).then_some(reservation)
+    }
+
+    /// Drop every expired reservation. Returns how many there were.
+    pub(crate) fn expire(&mut self) -> usize {
+        let now = Instant::now();
+        let count = self.held.len();
+        self.held.retain(|reservation| !reservation.is_expired(now));
+        count - self.held.len()
+    }
+}
+
+pub(crate) fn reserve<P: PackMut + ?Sized>(
+    pack: &mut P,
+    rect: Rect,
+    item: &str,
+    ttl: Option<Duration>,
+) -> Result<ReservationId, String> {
+    if rect.rows() == 0
+        || rect.cols() == 0
+        || rect.row() + rect.rows() > pack.rows()
+        || rect.col() + rect.cols() > pack.cols()
+    {
+        return Err(format!("{} is not within the pack", rect));
+    }
+    if pack.items_in(rect).next().is_some() {
+        return Err(format!("{} is not empty", rect));
+    }
+    if pack.reservations().blocks(&rect) {
+        return Err(format!("{} is already reserved", rect));
+    }
+    Ok(pack.reservations_mut().hold(rect, item, ttl))
+}
+
+pub(crate) fn release<P: PackMut + ?Sized>(pack: &mut P, id: ReservationId) -> bool {
+    pack.reservations_mut().take(id).is_some()
+}
+
+pub(crate) fn fulfill<P: PackMut + ?Sized>(
+    pack: &mut P,
+    id: ReservationId,
+    item: Item,
+) -> Result<Loc, String> {
+    let reservation = match pack.reservations_mut().take(id) {
+        Some(reservation) => reservation,
+        None => return Err("No such reservation".to_string()),
+    };
+    let rect = reservation.rect;
+    let result = if item.name() != reservation.item {
+        Err(format!("The cells are held for {}", reservation.item))
+    } else if (item.rows(), item.cols()) != (rect.rows(), rect.cols()) {
+        Err(format!("{} does not fit {}", item.name(), rect))
+    } else {
+        pack.add_item(item, rect.loc())
+    };
+    if result.is_err() {
+        pack.reservations_mut().held.push(reservation);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packs::{map_pack::MapPack, DensePack, Pack};
+
+    #[test]
+    fn reserved_cells_are_kept_for_their_item() {
+        let mut pack = DensePack::new(2, 3);
+        assert!(pack
+            .add_item(Item::new("coin", 1, 1, 'o'), Loc::new(1, 0))
+            .is_ok());
+        let rect = Rect::new(Loc::new(0, 1), 1, 2);
+        let id = pack.reserve(rect, "sword", None).unwrap();
+
+        assert!(pack
+            .add_item(Item::new("loot", 1, 1, 'l'), Loc::new(0, 2))
+            .is_err());
+        assert!(pack.move_item_at(Loc::new(1, 0), Loc::new(0, 1)).is_err());
+        assert!(!pack
+            .fit_positions(1, 1)
+            .iter()
+            .any(|fit| rect.contains(&fit.loc)));
+        assert!(pack
+            .reserve(Rect::new(Loc::new(0, 0), 1, 2), "axe", None)
+            .is_err());
+
+        assert!(pack.fulfill(id, Item::new("axe", 1, 2, 'a')).is_err());
+        assert!(pack.fulfill(id, Item::new("sword", 2, 1, 's')).is_err());
+        assert_eq!(
+            pack.fulfill(id, Item::new("sword", 1, 2, 's')),
+            Ok(Loc::new(0, 1))
+        );
+        assert!(pack.reservations().get(id).is_none());
+        assert_eq!(pack.to_string(), "| |s|s|\n|o| | |\n");
+    }
+
+    #[test]
+    fn released_cells_are_free_again() {
+        let mut pack = MapPack::new(1, 2);
+        let id = pack
+            .reserve(Rect::new(Loc::new(0, 0), 1, 2), "rope", None)
+            .unwrap();
+        assert!(pack
+            .add_item(Item::new("coin", 1, 1, 'o'), Loc::new(0, 1))
+            .is_err());
+
+        assert!(pack.release(id));
+        assert!(!pack.release(id));
+        assert!(pack
+            .add_item(Item::new("coin", 1, 1, 'o'), Loc::new(0, 1))
+            .is_ok());
+    }
+
+    #[test]
+    fn expired_reservations_block_nothing() {
+        let mut pack = DensePack::new(1, 2);
+        let hour = Some(Duration::from_secs(3600));
+        let kept = pack
+            .reserve(Rect::new(Loc::new(0, 1), 1, 1), "ore", hour)
+            .unwrap();
+        let rect = Rect::new(Loc::new(0, 0), 1, 1);
+        let lapsed = pack.reserve(rect, "gem", Some(Duration::ZERO)).unwrap();
+
+        assert!(pack.reservations().get(lapsed).is_none());
+        assert!(pack.reservations().get(kept).is_some());
+        assert_eq!(pack.expire_reservations(), 1);
+        assert!(pack.fulfill(lapsed, Item::new("gem", 1, 1, 'g')).is_err());
+        assert!(pack
+            .add_item(Item::new("coin", 1, 1, 'o'), Loc::new(0, 0))
+            .is_ok());
+        assert!(pack
+            .add_item(Item::new("pebble", 1, 1, 'p'), Loc::new(0, 1))
+            .is_err());
+    }
+}
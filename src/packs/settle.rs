@@ -0,0 +1,246 @@
+use crate::behavior::Motion;
+use crate::items::{Direction, Loc, PackedItem};
+use crate::packs::PackMut;
+
+/// An item that ended up somewhere else after an operation.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Displacement {
+    pub name: String,
+    pub from: Loc,
+    pub to: Loc,
+}
+
+/// How far `item` can slide towards `direction` before it would leave the
+/// pack or run into another item.
+fn farthest<P: PackMut + ?Sized>(pack: &P, item: &PackedItem, direction: Direction) -> Loc {
+    let mut dst = item.loc();
+    while let Some(next) = dst.step(direction) {
+        let motion = Motion {
+            dst: next,
+            transpose: false,
+        };
+        if !pack.motion_is_valid(item, motion) {
+            break;
+        }
+        dst = next;
+    }
+    dst
+}
+
+/// How close an item's leading edge is to the side of the pack it slides
+/// towards; items nearest that side move first so they make room.
+fn lead(item: &PackedItem, direction: Direction) -> i64 {
+    match direction {
+        Direction::Up => item.row() as i64,
+        Direction::Down => -((item.row() + item.rows()) as i64),
+        Direction::Left => item.col() as i64,
+        Direction::Right => -((item.col() + item.cols()) as i64),
+    }
+}
+
+pub(crate) fn settle<P: PackMut + ?Sized>(pack: &mut P, direction: Direction) -> Vec<Displacement> {
+    let mark = pack.history().recorded();
+    // Without hooks every pass moves some item at least one cell closer to
+    // the side, so this many passes always suffice. Hooks that redirect
+    // moves could keep items going round forever.
+    let extent = match direction {
+        Direction::Up | Direction::Down => pack.rows(),
+        Direction::Left | Direction::Right => pack.cols(),
+    };
+    let passes = pack.packed_items().count() * extent as usize + 1;
+
+    let mut displacements: Vec<Displacement> = Vec::new();
+    for _ in 0..passes {
+        let mut anchors: Vec<(i64, Loc)> = pack
+            .packed_items()
+            .map(|packed_item| (lead(packed_item, direction), packed_item.loc()))
+            .collect();
+        anchors.sort();
+
+        let mut moved = false;
+        for (_, src) in anchors {
+            let packed_item = pack.item_at(&src).unwrap();
+            let name = packed_item.name().to_string();
+            let dst = farthest(pack, packed_item, direction);
+            if dst == src {
+                continue;
+            }
+            // A hook may have sent the item somewhere other than `dst`.
+            let dst = match pack.move_item_at(src, dst) {
+                Ok(dst) if dst != src => dst,
+                _ => continue,
+            };
+            moved = true;
+
+            // An item moving again keeps its first origin.
+            match displacements.iter_mut().find(|shift| shift.to == src) {
+                Some(shift) => shift.to = dst,
+                None => displacements.push(Displacement {
+                    name,
+                    from: src,
+                    to: dst,
+                }),
+            }
+        }
+
+        if !moved {
+            break;
+        }
+    }
+    pack.history_mut().squash_since(mark);
+    // Hooks can bring an item back to where it started.
+    displacements.retain(|shift| shift.from != shift.to);
+    displacements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::behavior::{Behavior, ItemBehavior};
+    use crate::items::{Item, Kind};
+    use crate::packs::{map_pack::MapPack, DensePack, Pack};
+
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// Only ever goes one cell towards where it is sent.
+    struct Sluggish;
+
+    impl ItemBehavior for Sluggish {
+        fn on_move(
+            &self,
+            item: &PackedItem,
+            motion: &mut Motion,
+            _pack: &dyn Pack,
+        ) -> Result<(), String> {
+            let col = item.col() + u32::from(motion.dst.col() > item.col());
+            motion.dst = Loc::new(item.row(), col);
+            Ok(())
+        }
+    }
+
+    /// Swings between the first two columns, whatever it is told, and
+    /// counts its swings.
+    struct Pendulum(Rc<Cell<u32>>);
+
+    impl ItemBehavior for Pendulum {
+        fn on_move(
+            &self,
+            item: &PackedItem,
+            motion: &mut Motion,
+            _pack: &dyn Pack,
+        ) -> Result<(), String> {
+            self.0.set(self.0.get() + 1);
+            motion.dst = Loc::new(item.row(), u32::from(item.col() == 0));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn settle_down_stacks_items_on_the_floor() {
+        // |a| |      | | |
+        // | |b|  ->  |a| |
+        // | | |      |c|b|
+        // |c| |
+        let mut pack = DensePack::new(4, 2);
+        assert!(pack
+            .add_item(Item::new("a", 1, 1, 'a'), Loc::new(0, 0))
+            .is_ok());
+        assert!(pack
+            .add_item(Item::new("b", 1, 1, 'b'), Loc::new(1, 1))
+            .is_ok());
+        assert!(pack
+            .add_item(Item::new("c", 1, 1, 'c'), Loc::new(3, 0))
+            .is_ok());
+
+        let mut moved = pack.settle(Direction::Down);
+        moved.sort_by(|x, y| x.name.cmp(&y.name));
+        assert_eq!(
+            moved,
+            vec![
+                Displacement {
+                    name: "a".to_string(),
+                    from: Loc::new(0, 0),
+                    to: Loc::new(2, 0),
+                },
+                Displacement {
+                    name: "b".to_string(),
+                    from: Loc::new(1, 1),
+                    to: Loc::new(3, 1),
+                },
+            ]
+        );
+        assert!(pack.settle(Direction::Down).is_empty());
+    }
+
+    #[test]
+    fn settle_left_packs_each_row_against_the_wall() {
+        // |a|a| |b|      |a|a|b| |
+        // | |c|c| |  ->  |c|c| | |
+        let mut pack = MapPack::new(2, 4);
+        assert!(pack
+            .add_item(Item::new("a", 1, 2, 'a'), Loc::new(0, 0))
+            .is_ok());
+        assert!(pack
+            .add_item(Item::new("b", 1, 1, 'b'), Loc::new(0, 3))
+            .is_ok());
+        assert!(pack
+            .add_item(Item::new("c", 1, 2, 'c'), Loc::new(1, 1))
+            .is_ok());
+
+        let moved = pack.settle(Direction::Left);
+        assert_eq!(moved.len(), 2);
+        assert_eq!(pack.item_at(&Loc::new(0, 2)).unwrap().name(), "b");
+        assert_eq!(pack.item_at(&Loc::new(1, 0)).unwrap().name(), "c");
+    }
+
+    #[test]
+    fn settle_reports_where_hooks_actually_sent_items() {
+        let mut pack = DensePack::new(1, 4);
+        let snail = Item::new("snail", 1, 1, '@')
+            .with_kind(Kind::new("critter").with_behavior(Behavior::new(Sluggish)));
+        assert!(pack.add_item(snail, Loc::new(0, 0)).is_ok());
+
+        let moved = pack.settle(Direction::Right);
+        assert_eq!(
+            moved,
+            vec![Displacement {
+                name: "snail".to_string(),
+                from: Loc::new(0, 0),
+                to: Loc::new(0, 3),
+            }]
+        );
+        assert_eq!(pack.to_string(), "| | | |@|\n");
+
+        // The whole settle is one step of history.
+        assert!(pack.undo().is_ok());
+        assert_eq!(pack.to_string(), "|@| | | |\n");
+    }
+
+    #[test]
+    fn settle_stops_even_if_a_hook_keeps_items_moving() {
+        let mut pack = MapPack::new(1, 4);
+        let swings = Rc::new(Cell::new(0));
+        let bob = Item::new("bob", 1, 1, 'o')
+            .with_kind(Kind::new("weight").with_behavior(Behavior::new(Pendulum(swings.clone()))));
+        assert!(pack.add_item(bob, Loc::new(0, 0)).is_ok());
+
+        // One item across four columns allows five passes, each of which
+        // swings the bob once: to column 1, back to 0, 1, 0 and 1 again.
+        let moved = pack.settle(Direction::Right);
+        assert_eq!(swings.get(), 5);
+        assert_eq!(
+            moved,
+            vec![Displacement {
+                name: "bob".to_string(),
+                from: Loc::new(0, 0),
+                to: Loc::new(0, 1),
+            }]
+        );
+        assert!(pack.item_at(&Loc::new(0, 1)).is_some());
+        assert!(pack.undo().is_ok());
+        assert!(pack.item_at(&Loc::new(0, 0)).is_some());
+        assert!(pack.undo().is_ok());
+        assert_eq!(pack.iter().count(), 0);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::behavior::Motion;
+use crate::items::{Direction, Loc};
+use crate::packs::{Pack, PackMut};
+
+use std::collections::{HashMap, VecDeque};
+
+/// How a pack's `move_item_at` gets an item to its destination.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum MoveMode {
+    /// The item jumps straight to the destination.
+    #[default]
+    Teleport,
+    /// The item slides there one cell at a time, and every position on the
+    /// way has to be valid. Moves with no such path fail.
+    Sliding,
+}
+
+pub(crate) fn path_to<P: Pack + ?Sized>(pack: &P, loc: Loc, dst: Loc) -> Option<Vec<Direction>> {
+    let item = pack.item_at(&loc)?;
+    let start = item.loc();
+
+    // Breadth-first over anchors, remembering how each was first reached.
+    let mut reached: HashMap<Loc, Option<(Loc, Direction)>> = HashMap::new();
+    reached.insert(start, None);
+    let mut queue = VecDeque::from([start]);
+    while let Some(current) = queue.pop_front() {
+        if current == dst {
+            let mut path = Vec::new();
+            let mut at = current;
+            while let Some((previous, direction)) = reached[&at] {
+                path.push(direction);
+                at = previous;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for direction in Direction::ALL {
+            let Some(next) = current.step(direction) else {
+                continue;
+            };
+            let motion = Motion {
+                dst: next,
+                transpose: false,
+            };
+            if reached.contains_key(&next) || !pack.motion_is_valid(item, motion) {
+                continue;
+            }
+            reached.insert(next, Some((current, direction)));
+            queue.push_back(next);
+        }
+    }
+    None
+}
+
+/// Walk the item covering `src` along `path`, making each one-cell move
+/// with `step`. If any move fails the item goes back to where it started.
+/// The whole walk is kept in the history as one change. Returns the item's
+/// final anchor.
+pub(crate) fn follow<P, F>(
+    pack: &mut P,
+    src: Loc,
+    path: &[Direction],
+    step: F,
+) -> Result<Loc, String>
+where
+    P: PackMut + ?Sized,
+    F: Fn(&mut P, Loc, Loc) -> Result<Loc, String>,
+{
+    let Some(original) = pack.item_at(&src).cloned() else {
+        return Err("No item at the given location".to_string());
+    };
+    let mark = pack.history().recorded();
+    let held = pack.observers_mut().hold();
+    let mut current = original.loc();
+    for &direction in path {
+        let moved = match current.step(direction) {
+            Some(next) => step(pack, current, next),
+            None => Err("The item cannot slide past the edge of the pack".to_string()),
+        };
+        match moved {
+            Ok(loc) => current = loc,
+            Err(message) => {
+                pack.evict(current);
+                // Its starting spot is free again, so this cannot fail.
+                let _ = pack.restore(original);
+                pack.history_mut().forget_since(mark);
+                pack.observers_mut().discard(held);
+                return Err(message);
+            }
+        }
+    }
+    pack.history_mut().squash_since(mark);
+    pack.observers_mut().release();
+    Ok(current)
+}
+
+pub(crate) fn slide<P: PackMut + ?Sized>(
+    pack: &mut P,
+    loc: Loc,
+    direction: Direction,
+    steps: u32,
+) -> Result<Loc, String> {
+    let path = vec![direction; steps as usize];
+    follow(pack, loc, &path, |pack, from, to| {
+        pack.move_item_at(from, to)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::Item;
+    use crate::packs::{map_pack::MapPack, DensePack};
+
+    /// ```text
+    /// |s| | |
+    /// |w|w| |
+    /// | | | |
+    /// ```
+    fn maze<P: PackMut>(mut pack: P) -> P {
+        assert!(pack
+            .add_item(Item::new("slider", 1, 1, 's'), Loc::new(0, 0))
+            .is_ok());
+        assert!(pack
+            .add_item(Item::new("wall", 1, 2, 'w'), Loc::new(1, 0))
+            .is_ok());
+        pack
+    }
+
+    #[test]
+    fn path_goes_around_obstacles() {
+        let pack = maze(DensePack::new(3, 3));
+        assert_eq!(
+            pack.path_to(Loc::new(0, 0), Loc::new(2, 0)),
+            Some(vec![
+                Direction::Right,
+                Direction::Right,
+                Direction::Down,
+                Direction::Down,
+                Direction::Left,
+                Direction::Left,
+            ])
+        );
+        assert_eq!(pack.path_to(Loc::new(0, 0), Loc::new(0, 0)), Some(vec![]));
+        assert_eq!(pack.path_to(Loc::new(0, 0), Loc::new(1, 1)), None);
+    }
+
+    #[test]
+    fn slide_past_the_edge_leaves_item_in_place() {
+        let mut pack = maze(MapPack::new(3, 3));
+        assert_eq!(
+            pack.slide(Loc::new(0, 0), Direction::Right, 2),
+            Ok(Loc::new(0, 2))
+        );
+        assert!(pack.slide(Loc::new(0, 2), Direction::Down, 3).is_err());
+        assert_eq!(pack.item_at(&Loc::new(0, 2)).unwrap().name(), "slider");
+    }
+
+    #[test]
+    fn sliding_mode_refuses_to_jump_walls() {
+        let mut pack = maze(DensePack::new(3, 3).with_move_mode(MoveMode::Sliding));
+        let mut sealed = maze(MapPack::new(3, 2).with_move_mode(MoveMode::Sliding));
+
+        assert_eq!(
+            pack.move_item_at(Loc::new(0, 0), Loc::new(2, 0)),
+            Ok(Loc::new(2, 0))
+        );
+        assert!(sealed.move_item_at(Loc::new(0, 0), Loc::new(2, 0)).is_err());
+        assert_eq!(sealed.item_at(&Loc::new(0, 0)).unwrap().name(), "slider");
+    }
+}
@@ -0,0 +1,371 @@
+use crate::items::{Item, Loc, Orientation};
+use crate::packs::{space, Fit, Pack};
+
+use std::time::{Duration, Instant};
+
+/// How many search steps pass between checks of the time budget.
+const CLOCK_INTERVAL: u64 = 1024;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct SolveOptions {
+    pub allow_transpose: bool,
+    /// Give up after this long. Without a budget the search always runs to
+    /// an answer.
+    pub time_budget: Option<Duration>,
+}
+
+impl Default for SolveOptions {
+    fn default() -> Self {
+        SolveOptions {
+            allow_transpose: true,
+            time_budget: None,
+        }
+    }
+}
+
+/// Why a set of items cannot fit.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Infeasibility {
+    /// The named item is larger than the pack whichever way it is turned.
+    TooLarge(String),
+    /// The items cover more cells than the pack has free.
+    NotEnoughSpace { needed: u32, free: u32 },
+    /// Every arrangement was tried and none works. `steps` is how many
+    /// partial arrangements the search went through.
+    Exhausted { steps: u64 },
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Solution {
+    /// Where each item goes, in the order the items were given.
+    Fits(Vec<Fit>),
+    Infeasible(Infeasibility),
+    /// The time budget ran out before the search could decide.
+    TimedOut,
+}
+
+/// Items of the same footprint are interchangeable, so the search places
+/// shapes rather than items and never tries two orders of equal items.
+struct Shape {
+    rows: u32,
+    cols: u32,
+    /// Indices of the items of this shape, in the order given.
+    items: Vec<usize>,
+    /// Where each item of this shape placed so far went.
+    placed: Vec<Fit>,
+}
+
+impl Shape {
+    fn remaining(&self) -> usize {
+        self.items.len() - self.placed.len()
+    }
+
+    fn orientations(&self, allow_transpose: bool) -> Vec<(Orientation, u32, u32)> {
+        let mut orientations = vec![(Orientation::Upright, self.rows, self.cols)];
+        if allow_transpose && self.rows != self.cols {
+            orientations.push((Orientation::Transposed, self.cols, self.rows));
+        }
+        orientations
+    }
+}
+
+/// How long searches may run. One budget can be shared by several
+/// searches, which then stop once they have used it up between them.
+#[derive(Debug, Clone)]
+pub(crate) struct Budget {
+    deadline: Option<Instant>,
+    steps: u64,
+    spent: bool,
+}
+
+impl Budget {
+    /// A budget running out `time` from now, or never.
+    pub(crate) fn new(time: Option<Duration>) -> Self {
+        Budget {
+            deadline: time.map(|time| Instant::now() + time),
+            steps: 0,
+            spent: false,
+        }
+    }
+
+    /// Count a search step. False once the budget has run out.
+    fn step(&mut self) -> bool {
+        // The clock is read on the first step too, so a zero budget gives up
+        // straight away.
+        self.steps += 1;
+        if self.steps % CLOCK_INTERVAL == 1
+            && self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            self.spent = true;
+        }
+        !self.spent
+    }
+
+    /// How many partial arrangements the searches went through.
+    pub(crate) fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub(crate) fn is_spent(&self) -> bool {
+        self.spent
+    }
+}
+
+struct Search<'a> {
+    occupied: Vec<Vec<bool>>,
+    shapes: Vec<Shape>,
+    allow_transpose: bool,
+    budget: &'a mut Budget,
+}
+
+impl Search<'_> {
+    fn fits(&self, loc: Loc, rows: u32, cols: u32) -> bool {
+        let pack_rows = self.occupied.len() as u32;
+        let pack_cols = self.occupied[0].len() as u32;
+        loc.row() + rows <= pack_rows
+            && loc.col() + cols <= pack_cols
+            && (loc.row()..loc.row() + rows).all(|r| {
+                (loc.col()..loc.col() + cols).all(|c| !self.occupied[r as usize][c as usize])
+            })
+    }
+
+    fn fill(&mut self, loc: Loc, rows: u32, cols: u32, value: bool) {
+        for r in loc.row()..loc.row() + rows {
+            for c in loc.col()..loc.col() + cols {
+                self.occupied[r as usize][c as usize] = value;
+            }
+        }
+    }
+
+    /// The first free cell at or after `from` in reading order.
+    fn first_free(&self, from: Loc) -> Option<Loc> {
+        let cols = self.occupied[0].len() as u32;
+        let start = from.row() * cols + from.col();
+        (start..self.occupied.len() as u32 * cols)
+            .map(|i| Loc::new(i / cols, i % cols))
+            .find(|loc| !self.occupied[loc.row() as usize][loc.col() as usize])
+    }
+
+    /// Cover the first free cell with the top-left corner of some remaining
+    /// item, or leave it empty while there are cells to spare. Every
+    /// arrangement is reached this way exactly once.
+    fn place(&mut self, from: Loc, left: usize, slack: u32) -> bool {
+        if left == 0 {
+            return true;
+        }
+        if !self.budget.step() {
+            return false;
+        }
+        let Some(loc) = self.first_free(from) else {
+            return false;
+        };
+
+        for s in 0..self.shapes.len() {
+            if self.shapes[s].remaining() == 0 {
+                continue;
+            }
+            for (orientation, rows, cols) in self.shapes[s].orientations(self.allow_transpose) {
+                if !self.fits(loc, rows, cols) {
+                    continue;
+                }
+                self.fill(loc, rows, cols, true);
+                self.shapes[s].placed.push(Fit { loc, orientation });
+                if self.place(loc, left - 1, slack) {
+                    return true;
+                }
+                self.shapes[s].placed.pop();
+                self.fill(loc, rows, cols, false);
+            }
+        }
+
+        if slack > 0 {
+            self.fill(loc, 1, 1, true);
+            if self.place(loc, left, slack - 1) {
+                return true;
+            }
+            self.fill(loc, 1, 1, false);
+        }
+        false
+    }
+}
+
+/// Find a spot on the free cells of `occupied` for a footprint of each of
+/// `sizes`, given as rows and columns. Returns where each goes, in the order
+/// given, or None if they cannot all fit or the budget runs out first.
+pub(crate) fn arrange(
+    occupied: Vec<Vec<bool>>,
+    sizes: &[(u32, u32)],
+    allow_transpose: bool,
+    budget: &mut Budget,
+) -> Option<Vec<Fit>> {
+    let free = occupied.iter().flatten().filter(|&&cell| !cell).count() as u32;
+    let needed: u32 = sizes.iter().map(|&(rows, cols)| rows * cols).sum();
+    if needed > free {
+        return None;
+    }
+
+    let mut shapes: Vec<Shape> = Vec::new();
+    for (i, &(rows, cols)) in sizes.iter().enumerate() {
+        match shapes
+            .iter_mut()
+            .find(|shape| shape.rows == rows && shape.cols == cols)
+        {
+            Some(shape) => shape.items.push(i),
+            None => shapes.push(Shape {
+                rows,
+                cols,
+                items: vec![i],
+                placed: Vec::new(),
+            }),
+        }
+    }
+    // Big pieces first: they have the fewest places to go.
+    shapes.sort_by_key(|shape| std::cmp::Reverse(shape.rows * shape.cols));
+
+    let mut search = Search {
+        occupied,
+        shapes,
+        allow_transpose,
+        budget,
+    };
+    if !search.place(Loc::new(0, 0), sizes.len(), free - needed) {
+        return None;
+    }
+    let mut fits = vec![None; sizes.len()];
+    for shape in &search.shapes {
+        for (&i, &fit) in shape.items.iter().zip(&shape.placed) {
+            fits[i] = Some(fit);
+        }
+    }
+    Some(fits.into_iter().map(|fit| fit.unwrap()).collect())
+}
+
+pub(crate) fn solve<P: Pack + ?Sized>(pack: &P, items: &[Item], options: SolveOptions) -> Solution {
+    let mut budget = Budget::new(options.time_budget);
+    let occupied = space::blocked(pack);
+    let free = occupied.iter().flatten().filter(|&&cell| !cell).count() as u32;
+
+    for item in items {
+        let upright = item.rows() <= pack.rows() && item.cols() <= pack.cols();
+        let transposed = item.cols() <= pack.rows() && item.rows() <= pack.cols();
+        if !(upright || options.allow_transpose && transposed) {
+            return Solution::Infeasible(Infeasibility::TooLarge(item.name().to_string()));
+        }
+    }
+    let needed: u32 = items.iter().map(|item| item.rows() * item.cols()).sum();
+    if needed > free {
+        return Solution::Infeasible(Infeasibility::NotEnoughSpace { needed, free });
+    }
+
+    let sizes: Vec<(u32, u32)> = items
+        .iter()
+        .map(|item| (item.rows(), item.cols()))
+        .collect();
+    if let Some(fits) = arrange(occupied, &sizes, options.allow_transpose, &mut budget) {
+        return Solution::Fits(fits);
+    }
+    if budget.is_spent() {
+        return Solution::TimedOut;
+    }
+    Solution::Infeasible(Infeasibility::Exhausted {
+        steps: budget.steps(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::PackedItem;
+    use crate::packs::{map_pack::MapPack, DensePack, PackMut};
+
+    /// Add every item where the solution puts it, which fails if the
+    /// arrangement is not valid.
+    fn check<P: PackMut>(pack: &mut P, items: Vec<Item>, fits: &[Fit]) {
+        for (mut item, fit) in items.into_iter().zip(fits) {
+            if fit.orientation == Orientation::Transposed {
+                item.transpose();
+            }
+            assert!(pack.restore(PackedItem::new(fit.loc, item)).is_ok());
+        }
+    }
+
+    #[test]
+    fn tight_packing_is_found_around_existing_contents() {
+        // |x| | |      |x|b|b|
+        // | | | |  ->  |a|b|b|
+        // | | | |      |a|c|c|
+        let mut pack = DensePack::new(3, 3);
+        assert!(pack
+            .add_item(Item::new("x", 1, 1, 'x'), Loc::new(0, 0))
+            .is_ok());
+        let items = vec![
+            Item::new("a", 2, 1, 'a'),
+            Item::new("b", 2, 2, 'b'),
+            Item::new("c", 2, 1, 'c'),
+        ];
+
+        let Solution::Fits(fits) = pack.solve_fit(&items, SolveOptions::default()) else {
+            panic!("the items fit");
+        };
+        check(&mut pack, items, &fits);
+        assert_eq!(fits[2].orientation, Orientation::Transposed);
+    }
+
+    #[test]
+    fn rotation_can_be_forbidden() {
+        let mut pack = MapPack::new(1, 3);
+        let items = vec![Item::new("spear", 3, 1, '/')];
+
+        let options = SolveOptions {
+            allow_transpose: false,
+            ..SolveOptions::default()
+        };
+        assert_eq!(
+            pack.solve_fit(&items, options),
+            Solution::Infeasible(Infeasibility::TooLarge("spear".to_string()))
+        );
+        let Solution::Fits(fits) = pack.solve_fit(&items, SolveOptions::default()) else {
+            panic!("the spear fits sideways");
+        };
+        check(&mut pack, items, &fits);
+    }
+
+    #[test]
+    fn infeasibility_is_proven() {
+        // Four cells free, but no two 1x2 pieces fit the checkered gaps.
+        // |x| |
+        // | |x|
+        // | | |
+        let mut pack = DensePack::new(3, 2);
+        assert!(pack
+            .add_item(Item::new("x", 1, 1, 'x'), Loc::new(0, 0))
+            .is_ok());
+        assert!(pack
+            .add_item(Item::new("y", 1, 1, 'y'), Loc::new(1, 1))
+            .is_ok());
+        let pieces = vec![Item::new("p", 1, 2, 'p'), Item::new("q", 1, 2, 'q')];
+        assert!(matches!(
+            pack.solve_fit(&pieces, SolveOptions::default()),
+            Solution::Infeasible(Infeasibility::Exhausted { .. })
+        ));
+
+        let crowd = vec![Item::new("big", 2, 2, 'b'), Item::new("bigger", 2, 2, 'B')];
+        assert_eq!(
+            pack.solve_fit(&crowd, SolveOptions::default()),
+            Solution::Infeasible(Infeasibility::NotEnoughSpace { needed: 8, free: 4 })
+        );
+    }
+
+    #[test]
+    fn zero_budget_times_out() {
+        let pack = MapPack::new(5, 8);
+        let items: Vec<Item> = (0..13).map(|_| Item::new("bar", 1, 3, '=')).collect();
+        let options = SolveOptions {
+            allow_transpose: true,
+            time_budget: Some(Duration::ZERO),
+        };
+        assert_eq!(pack.solve_fit(&items, options), Solution::TimedOut);
+    }
+}
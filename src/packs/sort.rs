@@ -0,0 +1,218 @@
+use crate::behavior::Motion;
+use crate::items::{Loc, PackedItem};
+use crate::packs::Pack;
+
+use std::cmp::Ordering;
+
+/// Which items are kept together when sorting.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum Grouping {
+    #[default]
+    None,
+    Kind,
+    /// Items are grouped by their first tag.
+    Tag,
+}
+
+/// How items are ordered within a group.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum SortOrder {
+    /// Largest area first.
+    #[default]
+    Size,
+    /// Alphabetically by name.
+    Name,
+    /// Most valuable first.
+    Value,
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct SortKey {
+    pub group: Grouping,
+    pub order: SortOrder,
+}
+
+/// How the sorted items are laid back into the pack. Each item goes to the
+/// first free spot found scanning in the given direction, transposed only
+/// if it does not fit upright anywhere.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum SortStrategy {
+    /// Fill the top row from left to right, then the next row.
+    #[default]
+    RowMajor,
+    /// Fill the left column from top to bottom, then the next column.
+    ColumnMajor,
+}
+
+fn group_of(item: &PackedItem, grouping: Grouping) -> Option<&str> {
+    match grouping {
+        Grouping::None => None,
+        Grouping::Kind => item.kind(),
+        Grouping::Tag => item.tags().first().map(|tag| tag.as_str()),
+    }
+}
+
+fn compare(a: &PackedItem, b: &PackedItem, key: SortKey) -> Ordering {
+    // Ungrouped items go after every group.
+    let group = match (group_of(a, key.group), group_of(b, key.group)) {
+        (Some(a), Some(b)) => a.cmp(b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    };
+    let order = match key.order {
+        SortOrder::Size => (b.rows() * b.cols(), b.rows()).cmp(&(a.rows() * a.cols(), a.rows())),
+        SortOrder::Name => a.name().cmp(b.name()),
+        SortOrder::Value => b.value().cmp(&a.value()),
+    };
+    group
+        .then(order)
+        .then_with(|| a.name().cmp(b.name()))
+        .then_with(|| a.loc().cmp(&b.loc()))
+}
+
+/// The first anchor, scanning in `strategy` order, where a `rows` x `cols`
+/// block fits on the free cells of `occupied`.
+fn first_fit(occupied: &[Vec<bool>], rows: u32, cols: u32, strategy: SortStrategy) -> Option<Loc> {
+    let pack_rows = occupied.len() as u32;
+    let pack_cols = occupied.first().map_or(0, |row| row.len()) as u32;
+    if rows > pack_rows || cols > pack_cols {
+        return None;
+    }
+
+    let fits = |loc: Loc| -> bool {
+        (loc.row()..loc.row() + rows)
+            .all(|r| (loc.col()..loc.col() + cols).all(|c| !occupied[r as usize][c as usize]))
+    };
+    let anchors: Vec<Loc> = match strategy {
+        SortStrategy::RowMajor => (0..=pack_rows - rows)
+            .flat_map(|r| (0..=pack_cols - cols).map(move |c| Loc::new(r, c)))
+            .collect(),
+        SortStrategy::ColumnMajor => (0..=pack_cols - cols)
+            .flat_map(|c| (0..=pack_rows - rows).map(move |r| Loc::new(r, c)))
+            .collect(),
+    };
+    anchors.into_iter().find(|&loc| fits(loc))
+}
+
+/// The motions that lay the pack's items out sorted by `key`, or an error
+/// if the sorted arrangement does not fit.
+pub(crate) fn plan<P: Pack + ?Sized>(
+    pack: &P,
+    key: SortKey,
+    strategy: SortStrategy,
+) -> Result<Vec<(Loc, Motion)>, String> {
+    let mut items: Vec<&PackedItem> = pack.packed_items().collect();
+    items.sort_by(|a, b| compare(a, b, key));
+
+    let mut occupied = vec![vec![false; pack.cols() as usize]; pack.rows() as usize];
+    let mut motions = Vec::new();
+    for item in items {
+        let (dst, transpose, rows, cols) =
+            match first_fit(&occupied, item.rows(), item.cols(), strategy) {
+                Some(dst) => (dst, false, item.rows(), item.cols()),
+                None => match first_fit(&occupied, item.cols(), item.rows(), strategy) {
+                    Some(dst) => (dst, true, item.cols(), item.rows()),
+                    None => return Err("The sorted items do not fit in the pack.".to_string()),
+                },
+            };
+
+        for r in dst.row()..dst.row() + rows {
+            for c in dst.col()..dst.col() + cols {
+                occupied[r as usize][c as usize] = true;
+            }
+        }
+        if dst != item.loc() || transpose {
+            motions.push((item.loc(), Motion { dst, transpose }));
+        }
+    }
+    Ok(motions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::Item;
+    use crate::packs::{map_pack::MapPack, DensePack, PackMut};
+
+    #[test]
+    fn sort_by_kind_then_value_packs_groups_in_reading_order() {
+        let mut pack = DensePack::new(2, 3);
+        let items = [
+            (
+                Item::new("ruby", 1, 1, 'r').with_kind("gem").with_value(50),
+                Loc::new(1, 2),
+            ),
+            (
+                Item::new("axe", 1, 1, 'a').with_kind("tool").with_value(5),
+                Loc::new(0, 0),
+            ),
+            (
+                Item::new("opal", 1, 1, 'o').with_kind("gem").with_value(90),
+                Loc::new(1, 0),
+            ),
+            (Item::new("rock", 1, 1, '*'), Loc::new(0, 2)),
+        ];
+        for (item, loc) in items {
+            assert!(pack.add_item(item, loc).is_ok());
+        }
+
+        let key = SortKey {
+            group: Grouping::Kind,
+            order: SortOrder::Value,
+        };
+        assert!(pack.sort_by(key, SortStrategy::RowMajor).is_ok());
+
+        let names: Vec<&str> = pack.iter().map(|item| item.name()).collect();
+        assert_eq!(names, vec!["opal", "ruby", "axe", "rock"]);
+        assert_eq!(pack.item_at(&Loc::new(0, 0)).unwrap().name(), "opal");
+        assert_eq!(pack.item_at(&Loc::new(1, 0)).unwrap().name(), "rock");
+    }
+
+    #[test]
+    fn sort_by_size_column_major_fills_columns_first() {
+        let mut pack = MapPack::new(2, 3);
+        assert!(pack
+            .add_item(Item::new("pebble", 1, 1, '.'), Loc::new(0, 0))
+            .is_ok());
+        assert!(pack
+            .add_item(Item::new("stick", 2, 1, '|'), Loc::new(0, 2))
+            .is_ok());
+
+        assert!(pack
+            .sort_by(SortKey::default(), SortStrategy::ColumnMajor)
+            .is_ok());
+        assert_eq!(pack.item_at(&Loc::new(0, 0)).unwrap().name(), "stick");
+        assert_eq!(pack.item_at(&Loc::new(1, 0)).unwrap().name(), "stick");
+        assert_eq!(pack.item_at(&Loc::new(0, 1)).unwrap().name(), "pebble");
+    }
+
+    #[test]
+    fn sort_that_does_not_fit_leaves_pack_unchanged() {
+        // |a|b|b|      Sorting by name column by column would give
+        // |c|b|b|      |a| |c|
+        // |c|d|d|      |b|b|c|
+        //              |b|b| |  which leaves no room for 'd'.
+        let mut pack = DensePack::new(3, 3);
+        assert!(pack
+            .add_item(Item::new("a", 1, 1, 'a'), Loc::new(0, 0))
+            .is_ok());
+        assert!(pack
+            .add_item(Item::new("b", 2, 2, 'b'), Loc::new(0, 1))
+            .is_ok());
+        assert!(pack
+            .add_item(Item::new("c", 2, 1, 'c'), Loc::new(1, 0))
+            .is_ok());
+        assert!(pack
+            .add_item(Item::new("d", 1, 2, 'd'), Loc::new(2, 1))
+            .is_ok());
+        let before = pack.to_string();
+
+        let key = SortKey {
+            group: Grouping::None,
+            order: SortOrder::Name,
+        };
+        assert!(pack.sort_by(key, SortStrategy::ColumnMajor).is_err());
+        assert_eq!(pack.to_string(), before);
+    }
+}
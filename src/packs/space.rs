@@ -0,0 +1,194 @@
+use crate::items::{Item, Loc, Orientation, PackedItem, Rect};
+use crate::packs::Pack;
+
+/// A spot where an item of some size can be placed, and whether it has to
+/// be transposed to go there.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, PartialOrd, Ord)]
+pub struct Fit {
+    pub loc: Loc,
+    pub orientation: Orientation,
+}
+
+/// Which cells of the pack are covered by an item, indexed `[row][col]`.
+pub(crate) fn occupancy<P: Pack + ?Sized>(pack: &P) -> Vec<Vec<bool>> {
+    let mut occupied = vec![vec![false; pack.cols() as usize]; pack.rows() as usize];
+    for packed_item in pack.packed_items() {
+        for r in packed_item.row()..packed_item.row() + packed_item.rows() {
+            for c in packed_item.col()..packed_item.col() + packed_item.cols() {
+                occupied[r as usize][c as usize] = true;
+            }
+        }
+    }
+    occupied
+}
+
+/// Which cells of the pack are covered by an item or reserved, and so
+/// unavailable to new placements.
+pub(crate) fn blocked<P: Pack + ?Sized>(pack: &P) -> Vec<Vec<bool>> {
+    let mut blocked = occupancy(pack);
+    for rect in pack.reserved() {
+        for r in rect.row()..rect.row() + rect.rows() {
+            for c in rect.col()..rect.col() + rect.cols() {
+                blocked[r as usize][c as usize] = true;
+            }
+        }
+    }
+    blocked
+}
+
+/// Every empty rectangle that cannot grow in any direction without running
+/// into an item or the edge of the pack, in reading order of their anchors.
+pub(crate) fn free_rectangles<P: Pack + ?Sized>(pack: &P) -> Vec<Rect> {
+    let occupied = blocked(pack);
+    let rows = pack.rows() as usize;
+    let cols = pack.cols() as usize;
+
+    // run[r][c] is the number of free cells starting at (r, c) going right.
+    let mut run = vec![vec![0usize; cols + 1]; rows];
+    for r in 0..rows {
+        for c in (0..cols).rev() {
+            if !occupied[r][c] {
+                run[r][c] = run[r][c + 1] + 1;
+            }
+        }
+    }
+
+    let column_is_free =
+        |c: usize, top: usize, bottom: usize| -> bool { (top..=bottom).all(|r| !occupied[r][c]) };
+    let row_is_free = |r: usize, left: usize, width: usize| -> bool {
+        (left..left + width).all(|c| !occupied[r][c])
+    };
+
+    let mut rectangles = Vec::new();
+    for top in 0..rows {
+        for left in 0..cols {
+            let mut width = usize::MAX;
+            for (bottom, bottom_run) in run.iter().enumerate().skip(top) {
+                // Widest rectangle spanning rows top..=bottom from this left
+                // edge; it can never grow to the right.
+                width = width.min(bottom_run[left]);
+                if width == 0 {
+                    break;
+                }
+                let grows_left = left > 0 && column_is_free(left - 1, top, bottom);
+                let grows_up = top > 0 && row_is_free(top - 1, left, width);
+                let grows_down = bottom + 1 < rows && row_is_free(bottom + 1, left, width);
+                if !grows_left && !grows_up && !grows_down {
+                    rectangles.push(Rect::new(
+                        Loc::new(top as u32, left as u32),
+                        (bottom - top + 1) as u32,
+                        width as u32,
+                    ));
+                }
+            }
+        }
+    }
+    rectangles
+}
+
+/// Every anchor where a `rows` x `cols` item could be added, upright or
+/// transposed, in reading order.
+pub(crate) fn fit_positions<P: Pack + ?Sized>(pack: &P, rows: u32, cols: u32) -> Vec<Fit> {
+    let mut orientations = vec![(Orientation::Upright, rows, cols)];
+    if rows != cols {
+        orientations.push((Orientation::Transposed, cols, rows));
+    }
+
+    let mut fits = Vec::new();
+    for r in 0..pack.rows() {
+        for c in 0..pack.cols() {
+            let loc = Loc::new(r, c);
+            for &(orientation, rows, cols) in &orientations {
+                let tentative = PackedItem::new(loc, Item::new("", rows, cols, ' '));
+                if pack.placement_is_valid(&tentative) {
+                    fits.push(Fit { loc, orientation });
+                }
+            }
+        }
+    }
+    fits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packs::{map_pack::MapPack, DensePack};
+
+    #[test]
+    fn empty_pack_is_one_free_rectangle() {
+        let pack = DensePack::new(3, 4);
+        assert_eq!(
+            pack.free_rectangles(),
+            vec![Rect::new(Loc::new(0, 0), 3, 4)]
+        );
+    }
+
+    #[test]
+    fn free_rectangles_wrap_around_an_item() {
+        let mut pack = MapPack::new(3, 3);
+        let result = pack.add_item(Item::new("stone", 1, 1, '*'), Loc::new(1, 1));
+        assert!(result.is_ok());
+
+        let mut rectangles = pack.free_rectangles();
+        rectangles.sort();
+        assert_eq!(
+            rectangles,
+            vec![
+                Rect::new(Loc::new(0, 0), 1, 3),
+                Rect::new(Loc::new(0, 0), 3, 1),
+                Rect::new(Loc::new(0, 2), 3, 1),
+                Rect::new(Loc::new(2, 0), 1, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn full_pack_has_no_free_rectangles_or_fits() {
+        let mut pack = DensePack::new(2, 2);
+        let result = pack.add_item(Item::new("stone", 2, 2, '*'), Loc::new(0, 0));
+        assert!(result.is_ok());
+
+        assert!(pack.free_rectangles().is_empty());
+        assert!(pack.fit_positions(1, 1).is_empty());
+    }
+
+    #[test]
+    fn fit_positions_offer_transposed_placements() {
+        let mut pack = DensePack::new(2, 3);
+        let result = pack.add_item(Item::new("stone", 1, 1, '*'), Loc::new(0, 0));
+        assert!(result.is_ok());
+
+        let fits = pack.fit_positions(1, 2);
+        assert_eq!(
+            fits,
+            vec![
+                Fit {
+                    loc: Loc::new(0, 1),
+                    orientation: Orientation::Upright
+                },
+                Fit {
+                    loc: Loc::new(0, 1),
+                    orientation: Orientation::Transposed
+                },
+                Fit {
+                    loc: Loc::new(0, 2),
+                    orientation: Orientation::Transposed
+                },
+                Fit {
+                    loc: Loc::new(1, 0),
+                    orientation: Orientation::Upright
+                },
+                Fit {
+                    loc: Loc::new(1, 1),
+                    orientation: Orientation::Upright
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn square_items_are_not_offered_twice() {
+        let pack = MapPack::new(1, 1);
+        assert_eq!(pack.fit_positions(1, 1).len(), 1);
+    }
+}
@@ -0,0 +1,169 @@
+use crate::items::Rect;
+use crate::packs::{space, Pack};
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+
+/// A summary of how full and how fragmented a pack is. A cell is free when
+/// no item covers it and it is not reserved.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PackStats {
+    pub occupied_cells: u32,
+    pub total_cells: u32,
+    pub item_count: usize,
+    /// The biggest empty rectangle, if the pack has any free cell.
+    pub largest_free_rect: Option<Rect>,
+    /// The number of disconnected regions of free cells. An empty pack has
+    /// one, a full pack none.
+    pub free_regions: usize,
+    pub tag_counts: BTreeMap<String, usize>,
+}
+
+impl PackStats {
+    pub fn fill_ratio(&self) -> f64 {
+        self.occupied_cells as f64 / self.total_cells as f64
+    }
+}
+
+impl fmt::Display for PackStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "occupied: {} / {} ({:.0}%)",
+            self.occupied_cells,
+            self.total_cells,
+            self.fill_ratio() * 100.0
+        )?;
+        writeln!(f, "items: {}", self.item_count)?;
+        match &self.largest_free_rect {
+            Some(rect) => writeln!(f, "largest free area: {}", rect)?,
+            None => writeln!(f, "largest free area: none")?,
+        }
+        writeln!(f, "free regions: {}", self.free_regions)?;
+        for (tag, count) in &self.tag_counts {
+            writeln!(f, "tag {}: {}", tag, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Count the 4-connected regions of free cells.
+fn count_free_regions(blocked: &[Vec<bool>]) -> usize {
+    let rows = blocked.len();
+    let cols = blocked.first().map_or(0, |row| row.len());
+    let mut seen = vec![vec![false; cols]; rows];
+    let mut regions = 0;
+
+    for r in 0..rows {
+        for c in 0..cols {
+            if blocked[r][c] || seen[r][c] {
+                continue;
+            }
+            regions += 1;
+            seen[r][c] = true;
+            let mut queue = VecDeque::from([(r, c)]);
+            while let Some((r, c)) = queue.pop_front() {
+                let neighbours = [
+                    (r.wrapping_sub(1), c),
+                    (r + 1, c),
+                    (r, c.wrapping_sub(1)),
+                    (r, c + 1),
+                ];
+                for (nr, nc) in neighbours {
+                    if nr < rows && nc < cols && !blocked[nr][nc] && !seen[nr][nc] {
+                        seen[nr][nc] = true;
+                        queue.push_back((nr, nc));
+                    }
+                }
+            }
+        }
+    }
+    regions
+}
+
+pub(crate) fn stats<P: Pack + ?Sized>(pack: &P) -> PackStats {
+    let occupied = space::occupancy(pack);
+    let occupied_cells = occupied
+        .iter()
+        .map(|row| row.iter().filter(|&&cell| cell).count() as u32)
+        .sum();
+
+    let mut item_count = 0;
+    let mut tag_counts = BTreeMap::new();
+    for packed_item in pack.packed_items() {
+        item_count += 1;
+        for tag in packed_item.tags() {
+            *tag_counts.entry(tag.clone()).or_insert(0) += 1;
+        }
+    }
+
+    PackStats {
+        occupied_cells,
+        total_cells: pack.rows() * pack.cols(),
+        item_count,
+        largest_free_rect: space::free_rectangles(pack)
+            .into_iter()
+            .max_by_key(|rect| rect.area()),
+        free_regions: count_free_regions(&space::blocked(pack)),
+        tag_counts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::{Item, Loc};
+    use crate::packs::{map_pack::MapPack, DensePack, PackMut};
+
+    #[test]
+    fn empty_pack_stats() {
+        let stats = DensePack::new(2, 3).stats();
+        assert_eq!(stats.occupied_cells, 0);
+        assert_eq!(stats.total_cells, 6);
+        assert_eq!(stats.item_count, 0);
+        assert_eq!(
+            stats.largest_free_rect,
+            Some(Rect::new(Loc::new(0, 0), 2, 3))
+        );
+        assert_eq!(stats.free_regions, 1);
+    }
+
+    #[test]
+    fn wall_of_items_splits_free_space_into_regions() {
+        let mut pack = MapPack::new(3, 3);
+        let stick = Item::new("stick", 3, 1, '|').with_tag("wood");
+        let result = pack.add_item(stick, Loc::new(0, 1));
+        assert!(result.is_ok());
+
+        let stats = pack.stats();
+        assert_eq!(stats.occupied_cells, 3);
+        assert_eq!(stats.item_count, 1);
+        assert_eq!(stats.free_regions, 2);
+        assert_eq!(stats.largest_free_rect.map(|rect| rect.area()), Some(3));
+        assert_eq!(stats.tag_counts.get("wood"), Some(&1));
+    }
+
+    #[test]
+    fn full_pack_has_no_free_regions() {
+        let mut pack = DensePack::new(1, 2);
+        let result = pack.add_item(Item::new("stick", 1, 2, '-'), Loc::new(0, 0));
+        assert!(result.is_ok());
+
+        let stats = pack.stats();
+        assert_eq!(stats.free_regions, 0);
+        assert_eq!(stats.largest_free_rect, None);
+        assert_eq!(stats.fill_ratio(), 1.0);
+    }
+
+    #[test]
+    fn reserved_cells_are_not_free() {
+        let mut pack = DensePack::new(2, 3);
+        let rect = Rect::new(Loc::new(0, 1), 2, 1);
+        assert!(pack.reserve(rect, "sword", None).is_ok());
+
+        let stats = pack.stats();
+        assert_eq!(stats.occupied_cells, 0);
+        assert_eq!(stats.free_regions, 2);
+        assert_eq!(stats.largest_free_rect.map(|rect| rect.area()), Some(2));
+    }
+}
@@ -0,0 +1,725 @@
+use crate::items::{Color, Item, Loc, Orientation, PackedItem, SavedItem};
+use crate::packs::{Pack, PackMut};
+
+use std::fmt::Write;
+
+// A pack is saved as nested blocks of `key: value` fields:
+//
+//   pack {
+//     rows: 2,
+//     cols: 3,
+//     items: [
+//       item {
+//         name: "rope",
+//         row: 0,
+//         col: 0,
+//         rows: 1,
+//         cols: 2,
+//         shape: 'r',
+//         fg: rgb(120, 80, 20),
+//         tags: ["tool"],
+//       },
+//     ],
+//   }
+//
+// Item sizes are as the item lies in the pack; a turned item also says
+// `orientation: transposed`. Optional fields are `orientation`, `fg`, `bg`
+// (a color name, `ansi(n)` or `rgb(r, g, b)`), `kind`, `description`,
+// `tags`, `value` and `weight`. Strings are double-quoted and shapes
+// single-quoted, both escaping with a backslash. Trailing commas are
+// allowed and `//` starts a comment. Behaviors are not saved.
+
+/// Write `pack` in the text format.
+pub fn to_text(pack: &dyn Pack) -> String {
+    let mut text = String::new();
+    let _ = writeln!(text, "pack {{");
+    let _ = writeln!(text, "  rows: {},", pack.rows());
+    let _ = writeln!(text, "  cols: {},", pack.cols());
+    if pack.iter().next().is_none() {
+        let _ = writeln!(text, "  items: [],");
+    } else {
+        let _ = writeln!(text, "  items: [");
+        for packed_item in pack.iter() {
+            write_item(&mut text, packed_item);
+        }
+        let _ = writeln!(text, "  ],");
+    }
+    let _ = writeln!(text, "}}");
+    text
+}
+
+fn write_item(text: &mut String, packed_item: &PackedItem) {
+    let mut fields = vec![
+        ("name", quote(packed_item.name(), '"')),
+        ("row", packed_item.row().to_string()),
+        ("col", packed_item.col().to_string()),
+        ("rows", packed_item.rows().to_string()),
+        ("cols", packed_item.cols().to_string()),
+        ("shape", quote(packed_item.symbol(), '\'')),
+    ];
+    if packed_item.orientation() == Orientation::Transposed {
+        fields.push(("orientation", "transposed".to_string()));
+    }
+    if let Some(fg) = packed_item.fg() {
+        fields.push(("fg", color_text(fg)));
+    }
+    if let Some(bg) = packed_item.bg() {
+        fields.push(("bg", color_text(bg)));
+    }
+    if let Some(kind) = packed_item.kind() {
+        fields.push(("kind", quote(kind, '"')));
+    }
+    if let Some(description) = packed_item.description() {
+        fields.push(("description", quote(description, '"')));
+    }
+    if !packed_item.tags().is_empty() {
+        let tags: Vec<String> = packed_item
+            .tags()
+            .iter()
+            .map(|tag| quote(tag, '"'))
+            .collect();
+        fields.push(("tags", format!("[{}]", tags.join(", "))));
+    }
+    if packed_item.value() != 0 {
+        fields.push(("value", packed_item.value().to_string()));
+    }
+    if packed_item.weight() != 0 {
+        fields.push(("weight", packed_item.weight().to_string()));
+    }
+
+    let _ = writeln!(text, "    item {{");
+    for (key, value) in fields {
+        let _ = writeln!(text, "      {}: {},", key, value);
+    }
+    let _ = writeln!(text, "    }},");
+}
+
+fn quote(text: &str, delimiter: char) -> String {
+    let mut quoted = String::new();
+    quoted.push(delimiter);
+    for c in text.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if c == delimiter => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push(delimiter);
+    quoted
+}
+
+fn color_text(color: Color) -> String {
+    match color {
+        Color::Ansi(index) => format!("ansi({})", index),
+        Color::Rgb(r, g, b) => format!("rgb({}, {}, {})", r, g, b),
+        color => color.name().unwrap_or_default().to_string(),
+    }
+}
+
+/// Where something starts in the text, counting from 1.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct Pos {
+    line: usize,
+    col: usize,
+}
+
+fn error_at(pos: Pos, message: &str) -> String {
+    format!("line {}, column {}: {}", pos.line, pos.col, message)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(u64),
+    Str(String),
+    Shape(String),
+    Punct(char),
+    End,
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Ident(ident) => format!("`{}`", ident),
+        Token::Number(number) => format!("`{}`", number),
+        Token::Str(_) => "a string".to_string(),
+        Token::Shape(_) => "a shape".to_string(),
+        Token::Punct(c) => format!("`{}`", c),
+        Token::End => "the end of the text".to_string(),
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<(Token, Pos)>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut pos = Pos { line: 1, col: 1 };
+    let advance = |c: char, pos: &mut Pos| {
+        if c == '\n' {
+            pos.line += 1;
+            pos.col = 1;
+        } else {
+            pos.col += 1;
+        }
+    };
+
+    while let Some(&c) = chars.peek() {
+        let start = pos;
+        if c.is_whitespace() {
+            chars.next();
+            advance(c, &mut pos);
+        } else if c == '/' {
+            chars.next();
+            advance(c, &mut pos);
+            if chars.peek() != Some(&'/') {
+                return Err(error_at(start, "unexpected `/`"));
+            }
+            while let Some(&c) = chars.peek() {
+                if c == '\n' {
+                    break;
+                }
+                chars.next();
+                advance(c, &mut pos);
+            }
+        } else if c.is_ascii_digit() {
+            let mut digits = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                digits.push(c);
+                chars.next();
+                advance(c, &mut pos);
+            }
+            let number = digits
+                .parse()
+                .map_err(|_| error_at(start, "number too large"))?;
+            tokens.push((Token::Number(number), start));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut ident = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_alphanumeric() || **c == '_') {
+                ident.push(c);
+                chars.next();
+                advance(c, &mut pos);
+            }
+            tokens.push((Token::Ident(ident), start));
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            advance(c, &mut pos);
+            let mut quoted = String::new();
+            loop {
+                let escape_pos = pos;
+                let next = match chars.next() {
+                    Some(next) => next,
+                    None => return Err(error_at(start, "unterminated quote")),
+                };
+                advance(next, &mut pos);
+                if next == c {
+                    break;
+                }
+                if next != '\\' {
+                    quoted.push(next);
+                    continue;
+                }
+                match chars.next() {
+                    Some(escaped @ ('\\' | '"' | '\'')) => quoted.push(escaped),
+                    Some('n') => quoted.push('\n'),
+                    Some('t') => quoted.push('\t'),
+                    _ => return Err(error_at(escape_pos, "bad escape")),
+                }
+                pos.col += 1;
+            }
+            let token = if c == '"' {
+                Token::Str(quoted)
+            } else {
+                Token::Shape(quoted)
+            };
+            tokens.push((token, start));
+        } else if "{}[]():,".contains(c) {
+            chars.next();
+            advance(c, &mut pos);
+            tokens.push((Token::Punct(c), start));
+        } else {
+            return Err(error_at(start, &format!("unexpected `{}`", c)));
+        }
+    }
+    tokens.push((Token::End, pos));
+    Ok(tokens)
+}
+
+/// A parsed value, before it is given meaning.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Number(u64),
+    Str(String),
+    Shape(String),
+    Ident(String),
+    /// `name(1, 2, 3)`
+    Call(String, Vec<u64>),
+    List(Vec<(Value, Pos)>),
+    Block(Block),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Block {
+    kind: String,
+    pos: Pos,
+    fields: Vec<(String, Pos, Value, Pos)>,
+}
+
+struct Parser {
+    tokens: Vec<(Token, Pos)>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &(Token, Pos) {
+        &self.tokens[self.next]
+    }
+
+    fn bump(&mut self) -> (Token, Pos) {
+        let token = self.tokens[self.next].clone();
+        if self.next + 1 < self.tokens.len() {
+            self.next += 1;
+        }
+        token
+    }
+
+    fn expect(&mut self, c: char) -> Result<Pos, String> {
+        match self.bump() {
+            (Token::Punct(found), pos) if found == c => Ok(pos),
+            (token, pos) => Err(error_at(
+                pos,
+                &format!("expected `{}`, found {}", c, describe(&token)),
+            )),
+        }
+    }
+
+    /// Whether the next token is `c`, consuming it if so.
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek().0 == Token::Punct(c) {
+            self.bump();
+            return true;
+        }
+        false
+    }
+
+    fn block(&mut self, kind: String, pos: Pos) -> Result<Block, String> {
+        self.expect('{')?;
+        let mut fields: Vec<(String, Pos, Value, Pos)> = Vec::new();
+        while !self.eat('}') {
+            let (key, key_pos) = match self.bump() {
+                (Token::Ident(key), pos) => (key, pos),
+                (token, pos) => {
+                    return Err(error_at(
+                        pos,
+                        &format!("expected a field name, found {}", describe(&token)),
+                    ))
+                }
+            };
+            if fields.iter().any(|(other, ..)| *other == key) {
+                return Err(error_at(key_pos, &format!("`{}` is given twice", key)));
+            }
+            self.expect(':')?;
+            let (value, value_pos) = self.value()?;
+            fields.push((key, key_pos, value, value_pos));
+            if !self.eat(',') {
+                self.expect('}')?;
+                break;
+            }
+        }
+        Ok(Block { kind, pos, fields })
+    }
+
+    fn value(&mut self) -> Result<(Value, Pos), String> {
+        let (token, pos) = self.bump();
+        let value = match token {
+            Token::Number(number) => Value::Number(number),
+            Token::Str(text) => Value::Str(text),
+            Token::Shape(text) => Value::Shape(text),
+            Token::Punct('[') => {
+                let mut values = Vec::new();
+                while !self.eat(']') {
+                    values.push(self.value()?);
+                    if !self.eat(',') {
+                        self.expect(']')?;
+                        break;
+                    }
+                }
+                Value::List(values)
+            }
+            Token::Ident(ident) => match self.peek().0 {
+                Token::Punct('{') => Value::Block(self.block(ident, pos)?),
+                Token::Punct('(') => {
+                    self.bump();
+                    let mut args = Vec::new();
+                    while !self.eat(')') {
+                        match self.bump() {
+                            (Token::Number(number), _) => args.push(number),
+                            (token, pos) => {
+                                return Err(error_at(
+                                    pos,
+                                    &format!("expected a number, found {}", describe(&token)),
+                                ))
+                            }
+                        }
+                        if !self.eat(',') {
+                            self.expect(')')?;
+                            break;
+                        }
+                    }
+                    Value::Call(ident, args)
+                }
+                _ => Value::Ident(ident),
+            },
+            token => {
+                return Err(error_at(
+                    pos,
+                    &format!("expected a value, found {}", describe(&token)),
+                ))
+            }
+        };
+        Ok((value, pos))
+    }
+}
+
+/// The fields of a block, taken out one by one as they are interpreted so
+/// leftovers can be reported.
+struct Fields {
+    kind: String,
+    pos: Pos,
+    fields: Vec<(String, Pos, Value, Pos)>,
+}
+
+impl Fields {
+    fn new(value: Value, pos: Pos, kind: &str) -> Result<Self, String> {
+        match value {
+            Value::Block(block) if block.kind == kind => Ok(Fields {
+                kind: block.kind,
+                pos: block.pos,
+                fields: block.fields,
+            }),
+            _ => Err(error_at(pos, &format!("expected a `{}` block", kind))),
+        }
+    }
+
+    fn take(&mut self, key: &str) -> Option<(Value, Pos)> {
+        let idx = self.fields.iter().position(|(other, ..)| other == key)?;
+        let (_, _, value, pos) = self.fields.remove(idx);
+        Some((value, pos))
+    }
+
+    fn required(&mut self, key: &str) -> Result<(Value, Pos), String> {
+        self.take(key)
+            .ok_or_else(|| error_at(self.pos, &format!("`{}` is missing `{}`", self.kind, key)))
+    }
+
+    fn number<T: TryFrom<u64>>(&mut self, key: &str) -> Result<T, String> {
+        let (value, pos) = self.required(key)?;
+        number(value, pos)
+    }
+
+    fn optional_number<T: TryFrom<u64>>(&mut self, key: &str) -> Result<Option<T>, String> {
+        self.take(key)
+            .map(|(value, pos)| number(value, pos))
+            .transpose()
+    }
+
+    fn optional_str(&mut self, key: &str) -> Result<Option<String>, String> {
+        match self.take(key) {
+            None => Ok(None),
+            Some((Value::Str(text), _)) => Ok(Some(text)),
+            Some((_, pos)) => Err(error_at(pos, &format!("`{}` must be a string", key))),
+        }
+    }
+
+    fn optional_color(&mut self, key: &str) -> Result<Option<Color>, String> {
+        self.take(key)
+            .map(|(value, pos)| color(value, pos))
+            .transpose()
+    }
+
+    /// Every field must have been taken.
+    fn finish(self) -> Result<(), String> {
+        match self.fields.first() {
+            Some((key, pos, ..)) => Err(error_at(
+                *pos,
+                &format!("`{}` has no field `{}`", self.kind, key),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+fn number<T: TryFrom<u64>>(value: Value, pos: Pos) -> Result<T, String> {
+    match value {
+        Value::Number(number) => {
+            T::try_from(number).map_err(|_| error_at(pos, "number out of range"))
+        }
+        _ => Err(error_at(pos, "expected a number")),
+    }
+}
+
+fn color(value: Value, pos: Pos) -> Result<Color, String> {
+    let byte = |n: u64| u8::try_from(n).map_err(|_| error_at(pos, "color out of range"));
+    match value {
+        Value::Ident(name) => {
+            Color::named(&name).ok_or_else(|| error_at(pos, &format!("unknown color `{}`", name)))
+        }
+        Value::Call(name, args) => match (name.as_str(), args.as_slice()) {
+            ("ansi", [index]) => Ok(Color::Ansi(byte(*index)?)),
+            ("rgb", [r, g, b]) => Ok(Color::Rgb(byte(*r)?, byte(*g)?, byte(*b)?)),
+            _ => Err(error_at(pos, &format!("unknown color `{}`", name))),
+        },
+        _ => Err(error_at(pos, "expected a color")),
+    }
+}
+
+fn item(value: Value, pos: Pos) -> Result<(Item, Loc, Pos), String> {
+    let mut fields = Fields::new(value, pos, "item")?;
+    let name = match fields.required("name")? {
+        (Value::Str(name), _) => name,
+        (_, pos) => return Err(error_at(pos, "`name` must be a string")),
+    };
+    let loc = Loc::new(fields.number("row")?, fields.number("col")?);
+    let rows: u32 = fields.number("rows")?;
+    let cols: u32 = fields.number("cols")?;
+    let shape = match fields.required("shape")? {
+        (Value::Shape(shape), _) if !shape.is_empty() => shape,
+        (_, pos) => return Err(error_at(pos, "`shape` must be a quoted symbol like '*'")),
+    };
+    let orientation = match fields.take("orientation") {
+        None => Orientation::Upright,
+        Some((Value::Ident(ident), _)) if ident == "upright" => Orientation::Upright,
+        Some((Value::Ident(ident), _)) if ident == "transposed" => Orientation::Transposed,
+        Some((_, pos)) => {
+            return Err(error_at(
+                pos,
+                "`orientation` must be `upright` or `transposed`",
+            ))
+        }
+    };
+
+    let mut item = SavedItem {
+        name,
+        rows,
+        cols,
+        orientation,
+        symbol: shape,
+        ..SavedItem::default()
+    };
+    item.fg = fields.optional_color("fg")?;
+    item.bg = fields.optional_color("bg")?;
+    item.kind = fields.optional_str("kind")?;
+    item.description = fields.optional_str("description")?;
+    match fields.take("tags") {
+        None => {}
+        Some((Value::List(tags), _)) => {
+            for (tag, pos) in tags {
+                match tag {
+                    Value::Str(tag) => item.tags.push(tag),
+                    _ => return Err(error_at(pos, "tags must be strings")),
+                }
+            }
+        }
+        Some((_, pos)) => return Err(error_at(pos, "`tags` must be a list")),
+    }
+    item.value = fields.optional_number("value")?.unwrap_or_default();
+    item.weight = fields.optional_number("weight")?.unwrap_or_default();
+    let pos = fields.pos;
+    fields.finish()?;
+    Ok((item.build(), loc, pos))
+}
+
+/// Read a pack written in the text format, creating the empty pack with
+/// `new`. Items go in through `restore`, like a recovered journal: each
+/// placement is checked, but names are kept as written, duplicates
+/// included, whatever the pack's name policy, and nothing is recorded for
+/// undo. Errors give the line and column they were found at.
+pub fn from_text<P, F>(text: &str, new: F) -> Result<P, String>
+where
+    P: PackMut,
+    F: FnOnce(u32, u32) -> P,
+{
+    let mut parser = Parser {
+        tokens: tokenize(text)?,
+        next: 0,
+    };
+    let (value, pos) = parser.value()?;
+    match parser.bump() {
+        (Token::End, _) => {}
+        (token, pos) => {
+            return Err(error_at(
+                pos,
+                &format!("expected the end of the text, found {}", describe(&token)),
+            ))
+        }
+    }
+
+    let mut fields = Fields::new(value, pos, "pack")?;
+    let rows: u32 = fields.number("rows")?;
+    let cols: u32 = fields.number("cols")?;
+    if rows == 0 || cols == 0 {
+        return Err(error_at(
+            fields.pos,
+            "a pack needs at least one row and column",
+        ));
+    }
+    let items = match fields.required("items")? {
+        (Value::List(items), _) => items,
+        (_, pos) => return Err(error_at(pos, "`items` must be a list")),
+    };
+    fields.finish()?;
+
+    let mut pack = new(rows, cols);
+    for (value, pos) in items {
+        let (item, loc, pos) = item(value, pos)?;
+        pack.restore(PackedItem::new(loc, item))
+            .map_err(|message| error_at(pos, &message))?;
+    }
+    Ok(pack)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packs::{map_pack::MapPack, DensePack, NamePolicy};
+
+    #[test]
+    fn saving_then_loading_reproduces_the_layout() {
+        let mut pack = MapPack::new(3, 4);
+        let rope = Item::new("rope \"long\"", 1, 3, 'r')
+            .with_fg(Color::Rgb(120, 80, 20))
+            .with_bg(Color::Ansi(17))
+            .with_kind("tool")
+            .with_description("Coiled.\nSturdy.")
+            .with_tag("climbing")
+            .with_tag("it's")
+            .with_value(4)
+            .with_weight(2);
+        assert!(pack.add_item(rope, Loc::new(0, 0)).is_ok());
+        let heart = Item::new("heart", 1, 1, '?').with_symbol("\u{2764}\u{fe0f}");
+        assert!(pack.add_item(heart, Loc::new(1, 0)).is_ok());
+        assert!(pack
+            .add_item(Item::new("quote", 1, 1, '\''), Loc::new(2, 0))
+            .is_ok());
+        assert!(pack
+            .add_item(Item::new("pole", 1, 2, '|'), Loc::new(1, 1))
+            .is_ok());
+        assert!(pack.transpose_item(Loc::new(1, 1)).is_ok());
+
+        let text = to_text(&pack);
+        let loaded: MapPack = from_text(&text, MapPack::new).unwrap();
+        assert_eq!(loaded, pack);
+        assert_eq!(to_text(&loaded), text);
+    }
+
+    #[test]
+    fn writes_the_sketched_block_syntax() {
+        let empty = DensePack::new(1, 1);
+        assert_eq!(
+            to_text(&empty),
+            "pack {\n  rows: 1,\n  cols: 1,\n  items: [],\n}\n"
+        );
+
+        let mut pack = DensePack::new(1, 2);
+        assert!(pack
+            .add_item(Item::new("coin", 1, 1, '.'), Loc::new(0, 1))
+            .is_ok());
+        let expected = "\
+pack {
+  rows: 1,
+  cols: 2,
+  items: [
+    item {
+      name: \"coin\",
+      row: 0,
+      col: 1,
+      rows: 1,
+      cols: 1,
+      shape: '.',
+    },
+  ],
+}
+";
+        assert_eq!(to_text(&pack), expected);
+    }
+
+    #[test]
+    fn loading_accepts_comments_and_loose_commas() {
+        let text = "\
+// A tiny pack.
+pack { rows: 1, cols: 2, items: [
+  item { name: \"gem\", row: 0, col: 1, rows: 1, cols: 1, shape: 'g' }  // no comma
+] }";
+        let pack: DensePack = from_text(text, DensePack::new).unwrap();
+        assert_eq!(pack.to_string(), "| |g|\n");
+    }
+
+    #[test]
+    fn parse_errors_give_line_and_column() {
+        let load = |text: &str| from_text(text, DensePack::new).unwrap_err();
+
+        assert_eq!(
+            load("pack {\n  rows 1,\n}"),
+            "line 2, column 8: expected `:`, found `1`"
+        );
+        assert_eq!(
+            load("pack {\n  rows: 1,\n  cols: 1,\n}"),
+            "line 1, column 1: `pack` is missing `items`"
+        );
+        assert_eq!(
+            load("pack { rows: 1, cols: 1, items: [], size: 2 }"),
+            "line 1, column 37: `pack` has no field `size`"
+        );
+        assert_eq!(
+            load("pack { rows: 1, cols: 1, items: [ item { shape: \"*\" } ] }"),
+            "line 1, column 35: `item` is missing `name`"
+        );
+        assert_eq!(
+            load("pack {\n  rows: 1, cols: 1,\n  items: [ # ],\n}"),
+            "line 3, column 12: unexpected `#`"
+        );
+        assert_eq!(
+            load("pack { rows: 1, cols: 1, items: [], }\n!"),
+            "line 2, column 1: unexpected `!`"
+        );
+    }
+
+    #[test]
+    fn loading_checks_placement_like_add_item() {
+        let text = "\
+pack {
+  rows: 1,
+  cols: 2,
+  items: [
+    item { name: \"a\", row: 0, col: 0, rows: 1, cols: 2, shape: 'a' },
+    item { name: \"b\", row: 0, col: 1, rows: 1, cols: 1, shape: 'b' },
+  ],
+}";
+        assert_eq!(
+            from_text(text, DensePack::new).map(|pack: DensePack| pack.to_string()),
+            Err("line 6, column 5: Invalid item placement".to_string())
+        );
+    }
+
+    #[test]
+    fn loading_keeps_names_whatever_the_name_policy() {
+        let text = "\
+pack {
+  rows: 1,
+  cols: 2,
+  items: [
+    item { name: \"coin\", row: 0, col: 0, rows: 1, cols: 1, shape: 'o' },
+    item { name: \"coin\", row: 0, col: 1, rows: 1, cols: 1, shape: 'o' },
+  ],
+}";
+        let mut pack: MapPack = from_text(text, |rows, cols| {
+            MapPack::new(rows, cols).with_name_policy(NamePolicy::AutoSuffix)
+        })
+        .unwrap();
+        let names: Vec<&str> = pack.iter().map(|packed_item| packed_item.name()).collect();
+        assert_eq!(names, vec!["coin", "coin"]);
+        assert!(pack.undo().is_err());
+    }
+}